@@ -0,0 +1,134 @@
+use std::os::unix::io::AsRawFd;
+
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+
+use crate::*;
+
+/// Wait until the epoll fd of `buffer` becomes readable, then poll the
+/// buffer without blocking via `poll_nonblocking`, which returns the number of
+/// ready entries it processed.
+///
+/// Readiness is only cleared once polling came up empty, so that no wakeup
+/// is lost for data arriving in between.
+async fn consume_when_ready<T, F>(buffer: &mut AsyncFd<T>, mut poll_nonblocking: F) -> Result<()>
+where
+    T: AsRawFd,
+    F: FnMut(&mut T) -> i32,
+{
+    loop {
+        let mut guard = buffer.readable_mut().await.map_err(Error::from)?;
+        let ret = poll_nonblocking(guard.get_inner_mut());
+        if ret > 0 {
+            return Ok(());
+        }
+
+        let () = util::parse_ret(ret)?;
+        guard.clear_ready();
+    }
+}
+
+/// Register `fd_holder` with the tokio reactor of the current runtime.
+fn register<T>(fd_holder: T) -> Result<AsyncFd<T>>
+where
+    T: AsRawFd,
+{
+    AsyncFd::with_interest(fd_holder, Interest::READABLE)
+        .map_err(|err| Error::from(err).context("register buffer with tokio"))
+}
+
+/// An asynchronous wrapper around a [`RingBuffer`], for use with the
+/// [tokio](https://tokio.rs) runtime.
+///
+/// Instead of dedicating a thread to blocking in [`RingBuffer::poll()`],
+/// [`AsyncRingBuffer::consume()`] awaits data becoming available and then
+/// invokes the registered callbacks.
+///
+/// Only available with the `async` feature.
+#[derive(Debug)]
+pub struct AsyncRingBuffer<'a> {
+    inner: AsyncFd<RingBuffer<'a>>,
+}
+
+impl<'a> AsyncRingBuffer<'a> {
+    /// Wrap `ringbuf`, registering it with the tokio runtime this function
+    /// is called from.
+    ///
+    /// Fails if called outside of a tokio runtime with I/O enabled.
+    pub fn new(ringbuf: RingBuffer<'a>) -> Result<Self> {
+        Ok(Self {
+            inner: register(ringbuf)?,
+        })
+    }
+
+    /// Wait for data to become available in any of the ring buffers, then
+    /// consume it, calling the registered callbacks.
+    ///
+    /// This method is cancel safe: if the returned future is dropped before
+    /// completion, no data is consumed.
+    pub async fn consume(&mut self) -> Result<()> {
+        consume_when_ready(&mut self.inner, |ringbuf| unsafe {
+            libbpf_sys::ring_buffer__poll(ringbuf.ptr, 0)
+        })
+        .await
+    }
+
+    /// Retrieve the underlying [`RingBuffer`].
+    pub fn get_ref(&self) -> &RingBuffer<'a> {
+        self.inner.get_ref()
+    }
+
+    /// Deregister from the tokio runtime and return the underlying
+    /// [`RingBuffer`].
+    pub fn into_inner(self) -> RingBuffer<'a> {
+        self.inner.into_inner()
+    }
+}
+
+/// An asynchronous wrapper around a [`PerfBuffer`], for use with the
+/// [tokio](https://tokio.rs) runtime.
+///
+/// Instead of dedicating a thread to blocking in [`PerfBuffer::poll()`],
+/// [`AsyncPerfBuffer::consume()`] awaits data becoming available and then
+/// invokes the registered callbacks.
+///
+/// Only available with the `async` feature.
+#[derive(Debug)]
+pub struct AsyncPerfBuffer<'b> {
+    inner: AsyncFd<PerfBuffer<'b>>,
+}
+
+impl<'b> AsyncPerfBuffer<'b> {
+    /// Wrap `perfbuf`, registering it with the tokio runtime this function
+    /// is called from.
+    ///
+    /// Fails if called outside of a tokio runtime with I/O enabled.
+    pub fn new(perfbuf: PerfBuffer<'b>) -> Result<Self> {
+        Ok(Self {
+            inner: register(perfbuf)?,
+        })
+    }
+
+    /// Wait for data to become available in any of the per-cpu buffers,
+    /// then consume it, calling the registered callbacks.
+    ///
+    /// This method is cancel safe: if the returned future is dropped before
+    /// completion, no data is consumed.
+    pub async fn consume(&mut self) -> Result<()> {
+        consume_when_ready(&mut self.inner, |perfbuf| unsafe {
+            libbpf_sys::perf_buffer__poll(perfbuf.ptr, 0)
+        })
+        .await
+    }
+
+    /// Retrieve the underlying [`PerfBuffer`].
+    pub fn get_ref(&self) -> &PerfBuffer<'b> {
+        self.inner.get_ref()
+    }
+
+    /// Deregister from the tokio runtime and return the underlying
+    /// [`PerfBuffer`].
+    pub fn into_inner(self) -> PerfBuffer<'b> {
+        self.inner.into_inner()
+    }
+}
@@ -1,21 +1,261 @@
+use std::fmt::Display;
+use std::io;
 use std::result;
 
+use nix::errno::Errno;
 use thiserror::Error;
 
 /// Canonical error type for this crate.
+///
+/// Most operations wrap the errors they report in [`Error::Context`],
+/// describing the operation that failed. Instead of matching on
+/// [`Error::System`] directly, use [`Error::errno()`] or [`Error::kind()`],
+/// which look through any context.
 #[derive(Error, Debug)]
 pub enum Error {
     /// A system error occurred.
     #[error("System error, errno: {0}")]
     System(i32),
     /// An input was invalid.
-    #[error("Input input: {0}")]
+    #[error("Invalid input: {0}")]
     InvalidInput(String),
+    /// The kernel rejected a program when loading it.
+    #[error("Failed to load program {name}, errno: {errno}")]
+    ProgramLoad {
+        /// The name of the program.
+        name: String,
+        /// The errno the kernel reported.
+        errno: i32,
+        /// The verifier log of the program.
+        log: String,
+    },
     /// An internal error occurred.
     #[error("Internal error: {0}")]
     Internal(String),
+    /// An error occurred while performing the described operation, e.g.,
+    /// "update map `conn_table`".
+    #[error("{context}: {source}")]
+    Context {
+        /// A description of the operation that failed.
+        context: String,
+        /// The underlying error.
+        source: Box<Error>,
+    },
+    /// An I/O error that did not originate from this crate.
+    #[error(transparent)]
+    Io(io::Error),
+}
+
+impl Error {
+    /// Wrap this error, attaching a description of the operation that failed.
+    pub fn context<C: Display>(self, context: C) -> Self {
+        Error::Context {
+            context: context.to_string(),
+            source: Box::new(self),
+        }
+    }
+
+    /// Retrieve the errno describing this error, if any.
+    pub fn errno(&self) -> Option<i32> {
+        match self {
+            // Some code paths report errnos as negative values.
+            Error::System(errno) | Error::ProgramLoad { errno, .. } => Some(errno.abs()),
+            Error::Context { source, .. } => source.errno(),
+            Error::Io(err) => err.raw_os_error(),
+            Error::InvalidInput(_) | Error::Internal(_) => None,
+        }
+    }
+
+    /// Classify this error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::System(_) | Error::ProgramLoad { .. } => {
+                ErrorKind::from_errno(self.errno().unwrap_or_default())
+            }
+            Error::InvalidInput(_) => ErrorKind::InvalidInput,
+            Error::Internal(_) => ErrorKind::Other,
+            Error::Context { source, .. } => source.kind(),
+            Error::Io(err) => ErrorKind::from(err.kind()),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    /// Convert an [`io::Error`], recovering the original [`Error`] if it was
+    /// created from one.
+    fn from(err: io::Error) -> Self {
+        if let Some(errno) = err.raw_os_error() {
+            return Error::System(errno);
+        }
+
+        if err.get_ref().map_or(false, |inner| inner.is::<Error>()) {
+            // We just checked that there is an inner error of the right type,
+            // so neither of these can fail.
+            let inner = err.into_inner().unwrap();
+            return *inner.downcast::<Error>().unwrap();
+        }
+
+        Error::Io(err)
+    }
+}
+
+impl From<Error> for io::Error {
+    /// Convert an [`Error`] into an [`io::Error`] with a matching
+    /// [`io::ErrorKind`]. Converting the result back into an [`Error`] yields
+    /// the original error.
+    fn from(err: Error) -> Self {
+        match err {
+            Error::System(errno) if errno >= 0 => io::Error::from_raw_os_error(errno),
+            Error::Io(err) => err,
+            err => io::Error::new(err.kind().into(), err),
+        }
+    }
+}
+
+/// A classification of [`Error`]s, as retrieved via [`Error::kind()`].
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// An entity, e.g., a map element or a pinned object, was not found.
+    NotFound,
+    /// The operation lacked the necessary privileges.
+    PermissionDenied,
+    /// An entity already exists.
+    AlreadyExists,
+    /// The operation is not supported, e.g., by the running kernel.
+    Unsupported,
+    /// An input was invalid.
+    InvalidInput,
+    /// Any other error.
+    Other,
+}
+
+impl ErrorKind {
+    fn from_errno(errno: i32) -> Self {
+        match Errno::from_i32(errno) {
+            Errno::ENOENT => ErrorKind::NotFound,
+            Errno::EPERM | Errno::EACCES => ErrorKind::PermissionDenied,
+            Errno::EEXIST => ErrorKind::AlreadyExists,
+            Errno::EOPNOTSUPP | Errno::ENOSYS => ErrorKind::Unsupported,
+            Errno::EINVAL => ErrorKind::InvalidInput,
+            // The kernel internal ENOTSUPP, which leaks to user space in
+            // some cases.
+            _ if errno == 524 => ErrorKind::Unsupported,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl From<io::ErrorKind> for ErrorKind {
+    fn from(kind: io::ErrorKind) -> Self {
+        match kind {
+            io::ErrorKind::NotFound => ErrorKind::NotFound,
+            io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+            io::ErrorKind::AlreadyExists => ErrorKind::AlreadyExists,
+            io::ErrorKind::Unsupported => ErrorKind::Unsupported,
+            io::ErrorKind::InvalidInput => ErrorKind::InvalidInput,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl From<ErrorKind> for io::ErrorKind {
+    fn from(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::NotFound => io::ErrorKind::NotFound,
+            ErrorKind::PermissionDenied => io::ErrorKind::PermissionDenied,
+            ErrorKind::AlreadyExists => io::ErrorKind::AlreadyExists,
+            ErrorKind::Unsupported => io::ErrorKind::Unsupported,
+            ErrorKind::InvalidInput => io::ErrorKind::InvalidInput,
+            ErrorKind::Other => io::ErrorKind::Other,
+        }
+    }
+}
+
+/// Extension trait for attaching context to the error of a [`Result`].
+pub trait ErrorExt {
+    /// Wrap the error, if any, attaching a description of the operation that
+    /// failed. See [`Error::context()`].
+    fn context<C: Display>(self, context: C) -> Self;
+
+    /// Same as [`ErrorExt::context()`], except that the description is only
+    /// created in case of an error.
+    fn with_context<C: Display, F: FnOnce() -> C>(self, f: F) -> Self;
+}
+
+impl<T> ErrorExt for Result<T> {
+    fn context<C: Display>(self, context: C) -> Self {
+        self.map_err(|err| err.context(context))
+    }
+
+    fn with_context<C: Display, F: FnOnce() -> C>(self, f: F) -> Self {
+        self.map_err(|err| err.context(f()))
+    }
 }
 
 /// The result type used by this library, defaulting to [`Error`][crate::Error]
 /// as the error type.
 pub type Result<T> = result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_kind() {
+        assert_eq!(
+            Error::System(Errno::ENOENT as i32).kind(),
+            ErrorKind::NotFound
+        );
+        assert_eq!(
+            Error::System(-(Errno::EPERM as i32)).kind(),
+            ErrorKind::PermissionDenied
+        );
+        assert_eq!(
+            Error::System(Errno::EEXIST as i32)
+                .context("update map `conn_table`")
+                .kind(),
+            ErrorKind::AlreadyExists
+        );
+        assert_eq!(Error::System(524).kind(), ErrorKind::Unsupported);
+        assert_eq!(
+            Error::InvalidInput("foo".into()).kind(),
+            ErrorKind::InvalidInput
+        );
+        assert_eq!(Error::Internal("foo".into()).kind(), ErrorKind::Other);
+    }
+
+    #[test]
+    fn test_error_context() {
+        let err = Err::<(), _>(Error::System(Errno::ENOENT as i32))
+            .context("attach kprobe `do_sys_open`")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "attach kprobe `do_sys_open`: System error, errno: 2"
+        );
+        assert_eq!(err.errno(), Some(2));
+    }
+
+    #[test]
+    fn test_io_error_conversion() {
+        let err = io::Error::from(Error::System(Errno::EACCES as i32));
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(err.raw_os_error(), Some(Errno::EACCES as i32));
+        assert!(matches!(Error::from(err), Error::System(errno) if errno == Errno::EACCES as i32));
+
+        let err = io::Error::from(Error::InvalidInput("foo".into()).context("bar"));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        match Error::from(err) {
+            Error::Context { context, source } => {
+                assert_eq!(context, "bar");
+                assert!(matches!(*source, Error::InvalidInput(ref s) if s == "foo"));
+            }
+            err => panic!("unexpected error: {err:?}"),
+        }
+
+        let err = Error::from(io::Error::new(io::ErrorKind::NotFound, "foo"));
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert_eq!(io::Error::from(err).to_string(), "foo");
+    }
+}
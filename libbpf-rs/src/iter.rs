@@ -1,8 +1,83 @@
 use nix::{errno, libc, unistd};
+use plain::Plain;
 use std::io;
+use std::io::Read;
+use std::marker::PhantomData;
+use std::ptr;
 
 use crate::*;
 
+/// The order in which a cgroup iterator walks the cgroup hierarchy.
+#[non_exhaustive]
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CgroupIterOrder {
+    /// Visit only the given cgroup.
+    SelfOnly = libbpf_sys::BPF_CGROUP_ITER_SELF_ONLY,
+    /// Walk the given cgroup's descendants, visiting parents first.
+    DescendantsPre = libbpf_sys::BPF_CGROUP_ITER_DESCENDANTS_PRE,
+    /// Walk the given cgroup's descendants, visiting children first.
+    DescendantsPost = libbpf_sys::BPF_CGROUP_ITER_DESCENDANTS_POST,
+    /// Walk from the given cgroup up to the root.
+    AncestorsUp = libbpf_sys::BPF_CGROUP_ITER_ANCESTORS_UP,
+}
+
+/// Parameters selecting what an iterator attached via
+/// [`Program::attach_iter()`] iterates over.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IterOpts {
+    /// Iterate over the elements of the map with the given fd, e.g., for
+    /// `iter/bpf_map_elem` programs.
+    Map {
+        /// The fd of the map.
+        map_fd: i32,
+    },
+    /// Iterate over the thread with the given id only, for task iterators.
+    Tid(u32),
+    /// Iterate over the threads of the process with the given id only, for
+    /// task iterators.
+    Pid(u32),
+    /// Iterate over the threads of the process referred to by the given
+    /// pidfd only, for task iterators.
+    PidFd(i32),
+    /// Iterate over cgroups starting at the one with the given fd.
+    Cgroup {
+        /// The fd of the cgroup to start at.
+        cgroup_fd: i32,
+        /// The order to walk the hierarchy in.
+        order: CgroupIterOrder,
+    },
+    /// Iterate over cgroups starting at the one with the given id.
+    CgroupId {
+        /// The id of the cgroup to start at.
+        cgroup_id: u64,
+        /// The order to walk the hierarchy in.
+        order: CgroupIterOrder,
+    },
+}
+
+impl From<IterOpts> for libbpf_sys::bpf_iter_link_info {
+    fn from(opts: IterOpts) -> Self {
+        let mut linfo = libbpf_sys::bpf_iter_link_info::default();
+        match opts {
+            IterOpts::Map { map_fd } => linfo.map.map_fd = map_fd as u32,
+            IterOpts::Tid(tid) => linfo.task.tid = tid,
+            IterOpts::Pid(pid) => linfo.task.pid = pid,
+            IterOpts::PidFd(pid_fd) => linfo.task.pid_fd = pid_fd as u32,
+            IterOpts::Cgroup { cgroup_fd, order } => {
+                linfo.cgroup.cgroup_fd = cgroup_fd as u32;
+                linfo.cgroup.order = order as libbpf_sys::bpf_cgroup_iter_order;
+            }
+            IterOpts::CgroupId { cgroup_id, order } => {
+                linfo.cgroup.cgroup_id = cgroup_id;
+                linfo.cgroup.order = order as libbpf_sys::bpf_cgroup_iter_order;
+            }
+        }
+        linfo
+    }
+}
+
 /// Represents a bpf iterator for reading kernel data structures. This requires
 /// Linux 5.8.
 ///
@@ -24,6 +99,19 @@ impl Iter {
         }
         Ok(Self { fd })
     }
+
+    /// Read the iterator's output as a sequence of fixed-size records of
+    /// type `T`, as written by the program via `bpf_seq_write()`.
+    ///
+    /// Output ending with a partial record is reported as an error of kind
+    /// [`io::ErrorKind::UnexpectedEof`], wrapped in [`Error::Io`].
+    pub fn records<T: Plain>(&mut self) -> IterRecords<'_, T> {
+        IterRecords {
+            iter: self,
+            buf: vec![0; std::mem::size_of::<T>()],
+            _type: PhantomData,
+        }
+    }
 }
 
 impl io::Read for Iter {
@@ -41,3 +129,48 @@ impl Drop for Iter {
         let _ = unistd::close(self.fd);
     }
 }
+
+/// An iterator over the records of type `T` read from an [`Iter`], as
+/// created by [`Iter::records()`].
+#[derive(Debug)]
+pub struct IterRecords<'iter, T> {
+    iter: &'iter mut Iter,
+    buf: Vec<u8>,
+    _type: PhantomData<T>,
+}
+
+impl<T: Plain> Iterator for IterRecords<'_, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.is_empty() {
+            return None;
+        }
+
+        // Records may be split across reads, so collect them piece by piece.
+        let mut filled = 0;
+        while filled < self.buf.len() {
+            match self.iter.read(&mut self.buf[filled..]) {
+                Ok(0) if filled == 0 => return None,
+                Ok(0) => {
+                    return Some(Err(Error::from(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!(
+                            "iterator output ends with partial record of {} bytes",
+                            filled
+                        ),
+                    ))))
+                }
+                Ok(n) => filled += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Some(Err(Error::from(err))),
+            }
+        }
+
+        // SAFETY: `T` is `Plain`, so any sequence of bytes of its size is a
+        //         valid instance.
+        Some(Ok(unsafe {
+            ptr::read_unaligned(self.buf.as_ptr() as *const T)
+        }))
+    }
+}
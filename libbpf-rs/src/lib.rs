@@ -72,13 +72,17 @@
     rustdoc::broken_intra_doc_links
 )]
 
+#[cfg(feature = "async")]
+mod async_buffer;
 mod error;
 mod iter;
 mod link;
 mod linker;
+mod lpm_trie;
 mod map;
 mod object;
 mod perf_buffer;
+mod perf_event;
 mod print;
 mod program;
 pub mod query;
@@ -87,24 +91,42 @@ mod ringbuf;
 #[doc(hidden)]
 pub mod skeleton;
 mod tc;
+mod typed_map;
+mod user_ringbuf;
 mod util;
+mod xdp;
 
 pub use libbpf_sys;
+pub use plain;
 
-pub use crate::error::{Error, Result};
-pub use crate::iter::Iter;
+#[cfg(feature = "async")]
+pub use crate::async_buffer::{AsyncPerfBuffer, AsyncRingBuffer};
+pub use crate::error::{Error, ErrorExt, ErrorKind, Result};
+pub use crate::iter::{CgroupIterOrder, Iter, IterOpts, IterRecords};
 pub use crate::link::Link;
 pub use crate::linker::Linker;
-pub use crate::map::{Map, MapFlags, MapType, OpenMap};
+pub use crate::lpm_trie::{Ipv4LpmKey, Ipv6LpmKey, LpmKey, LpmTrie};
+pub use crate::map::{
+    BatchedMapIter, BatchedPercpuMapIter, Map, MapEntryIter, MapFlags, MapMmap, MapMmapMut,
+    MapPercpuEntryIter, MapType, OpenMap,
+};
 pub use crate::object::{Object, ObjectBuilder, OpenObject};
 pub use crate::perf_buffer::{PerfBuffer, PerfBufferBuilder};
+pub use crate::perf_event::{
+    BreakpointType, HardwareEvent, PerfEvent, PerfEventBuilder, SampleRate, SoftwareEvent,
+};
 pub use crate::print::{get_print, set_print, PrintCallback, PrintLevel};
 pub use crate::program::{
-    OpenProgram, Program, ProgramAttachType, ProgramType, TracepointOpts, UprobeOpts, UsdtOpts,
+    detach_reuseport, detach_socket, CgroupAttachOpts, KprobeMultiOpts, KprobeOpts, OpenProgram,
+    ProbeAttachMode, Program, ProgramAttachType, ProgramType, TestRunOpts, TestRunOutput,
+    TraceOpts, TracepointOpts, UprobeMultiOpts, UprobeOpts, UsdtOpts,
 };
-pub use crate::ringbuf::{RingBuffer, RingBufferBuilder};
+pub use crate::ringbuf::{Backpressure, RingBuffer, RingBufferBuilder, RingBufferReceiver};
 pub use crate::tc::{
     TcAttachPoint, TcHook, TcHookBuilder, TC_CUSTOM, TC_EGRESS, TC_H_CLSACT, TC_H_INGRESS,
     TC_H_MIN_EGRESS, TC_H_MIN_INGRESS, TC_INGRESS,
 };
+pub use crate::typed_map::{TypedMap, TypedPercpuMap};
+pub use crate::user_ringbuf::{UserRingBuffer, UserRingBufferSample};
 pub use crate::util::num_possible_cpus;
+pub use crate::xdp::{Xdp, XdpFlags, XdpQuery};
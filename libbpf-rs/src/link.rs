@@ -1,5 +1,9 @@
 use std::fmt::Debug;
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::ptr;
+
+use nix::{libc, unistd};
 
 use crate::*;
 
@@ -9,12 +13,38 @@ use crate::*;
 /// when this object is dropped if nothing else is holding a reference count.
 #[derive(Debug)]
 pub struct Link {
-    ptr: *mut libbpf_sys::bpf_link,
+    inner: LinkInner,
+}
+
+#[derive(Debug)]
+enum LinkInner {
+    /// A link managed by libbpf.
+    Libbpf(*mut libbpf_sys::bpf_link),
+    /// A link created without libbpf's involvement, because libbpf lacks
+    /// support for it, owning the fd of the kernel's link.
+    Fd {
+        fd: i32,
+        pin_path: Option<PathBuf>,
+        disconnected: bool,
+    },
 }
 
 impl Link {
     pub(crate) fn new(ptr: *mut libbpf_sys::bpf_link) -> Self {
-        Link { ptr }
+        Link {
+            inner: LinkInner::Libbpf(ptr),
+        }
+    }
+
+    /// Take ownership of the fd of a kernel link created by us.
+    pub(crate) fn from_fd(fd: i32) -> Self {
+        Link {
+            inner: LinkInner::Fd {
+                fd,
+                pin_path: None,
+                disconnected: false,
+            },
+        }
     }
 
     /// Create link from BPF FS file.
@@ -42,7 +72,7 @@ impl Link {
 
     /// Replace the underlying prog with `prog`.
     pub fn update_prog(&mut self, prog: Program) -> Result<()> {
-        let ret = unsafe { libbpf_sys::bpf_link__update_program(self.ptr, prog.ptr) };
+        let ret = unsafe { libbpf_sys::bpf_link_update(self.fd(), prog.fd(), ptr::null()) };
         util::parse_ret(ret)
     }
 
@@ -56,40 +86,74 @@ impl Link {
     /// exit of userspace program doesn't trigger automatic detachment and clean up
     /// inside the kernel.
     pub fn disconnect(&mut self) {
-        unsafe { libbpf_sys::bpf_link__disconnect(self.ptr) }
+        match &mut self.inner {
+            LinkInner::Libbpf(ptr) => unsafe { libbpf_sys::bpf_link__disconnect(*ptr) },
+            // Just like libbpf, we keep the link alive by not closing its fd.
+            LinkInner::Fd { disconnected, .. } => *disconnected = true,
+        }
     }
 
     /// [Pin](https://facebookmicrosites.github.io/bpf/blog/2018/08/31/object-lifetime.html#bpffs)
     /// this link to bpffs.
     pub fn pin<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        let path_c = util::path_to_cstring(path)?;
+        let path_c = util::path_to_cstring(&path)?;
         let path_ptr = path_c.as_ptr();
 
-        let ret = unsafe { libbpf_sys::bpf_link__pin(self.ptr, path_ptr) };
-        util::parse_ret(ret)
+        match &mut self.inner {
+            LinkInner::Libbpf(ptr) => {
+                let ret = unsafe { libbpf_sys::bpf_link__pin(*ptr, path_ptr) };
+                util::parse_ret(ret)
+            }
+            LinkInner::Fd { fd, pin_path, .. } => {
+                if pin_path.is_some() {
+                    return Err(Error::System(libc::EBUSY));
+                }
+                let ret = unsafe { libbpf_sys::bpf_obj_pin(*fd, path_ptr) };
+                let () = util::parse_ret(ret)?;
+                *pin_path = Some(path.as_ref().to_path_buf());
+                Ok(())
+            }
+        }
     }
 
     /// [Unpin](https://facebookmicrosites.github.io/bpf/blog/2018/08/31/object-lifetime.html#bpffs)
     /// from bpffs
     pub fn unpin(&mut self) -> Result<()> {
-        let ret = unsafe { libbpf_sys::bpf_link__unpin(self.ptr) };
-        util::parse_ret(ret)
+        match &mut self.inner {
+            LinkInner::Libbpf(ptr) => {
+                let ret = unsafe { libbpf_sys::bpf_link__unpin(*ptr) };
+                util::parse_ret(ret)
+            }
+            LinkInner::Fd { pin_path, .. } => {
+                let path = pin_path.as_ref().ok_or(Error::System(libc::EINVAL))?;
+                let () = fs::remove_file(path).map_err(Error::from)?;
+                *pin_path = None;
+                Ok(())
+            }
+        }
     }
 
     /// Returns the file descriptor of the link.
     #[deprecated(since = "0.17.0", note = "please use `fd` instead")]
     pub fn get_fd(&self) -> i32 {
-        unsafe { libbpf_sys::bpf_link__fd(self.ptr) }
+        self.fd()
     }
 
     /// Returns the file descriptor of the link.
     pub fn fd(&self) -> i32 {
-        unsafe { libbpf_sys::bpf_link__fd(self.ptr) }
+        match &self.inner {
+            LinkInner::Libbpf(ptr) => unsafe { libbpf_sys::bpf_link__fd(*ptr) },
+            LinkInner::Fd { fd, .. } => *fd,
+        }
     }
 
     /// Returns path to BPF FS file or `None` if not pinned.
     pub fn pin_path(&self) -> Option<PathBuf> {
-        let path_ptr = unsafe { libbpf_sys::bpf_link__pin_path(self.ptr) };
+        let ptr = match &self.inner {
+            LinkInner::Libbpf(ptr) => *ptr,
+            LinkInner::Fd { pin_path, .. } => return pin_path.clone(),
+        };
+        let path_ptr = unsafe { libbpf_sys::bpf_link__pin_path(ptr) };
         if path_ptr.is_null() {
             return None;
         }
@@ -104,13 +168,27 @@ impl Link {
 
     /// Detach the link.
     pub fn detach(&self) -> Result<()> {
-        let ret = unsafe { libbpf_sys::bpf_link__detach(self.ptr) };
+        let ret = match &self.inner {
+            LinkInner::Libbpf(ptr) => unsafe { libbpf_sys::bpf_link__detach(*ptr) },
+            LinkInner::Fd { fd, .. } => unsafe { libbpf_sys::bpf_link_detach(*fd) },
+        };
         util::parse_ret(ret)
     }
 }
 
 impl Drop for Link {
     fn drop(&mut self) {
-        let _ = unsafe { libbpf_sys::bpf_link__destroy(self.ptr) };
+        match &self.inner {
+            LinkInner::Libbpf(ptr) => {
+                let _ = unsafe { libbpf_sys::bpf_link__destroy(*ptr) };
+            }
+            LinkInner::Fd {
+                fd, disconnected, ..
+            } => {
+                if !disconnected {
+                    let _ = unistd::close(*fd);
+                }
+            }
+        }
     }
 }
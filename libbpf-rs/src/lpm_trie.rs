@@ -0,0 +1,293 @@
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::str::FromStr;
+
+use plain::Plain;
+
+use crate::typed_map::{pod_as_bytes, pod_from_bytes};
+use crate::*;
+
+/// A key of a [`MapType::LpmTrie`] map, mirroring the kernel's
+/// `struct bpf_lpm_trie_key` for a fixed size address.
+///
+/// Implemented by [`Ipv4LpmKey`] and [`Ipv6LpmKey`].
+///
+/// # Safety
+/// Implementors must not contain padding bytes, as keys are passed to the
+/// kernel as raw bytes.
+pub unsafe trait LpmKey: Plain + Copy + Debug {
+    /// The address type this key is made of.
+    type Addr: Copy;
+
+    /// The maximum prefix length, i.e., the number of bits of an address.
+    const MAX_PREFIX_LEN: u32;
+
+    /// Create a key from an address and the length of the prefix.
+    fn new(addr: Self::Addr, prefix_len: u32) -> Result<Self>;
+
+    /// The address of this key, with all bits beyond the prefix cleared.
+    fn addr(&self) -> Self::Addr;
+
+    /// The length of the prefix in bits.
+    fn prefix_len(&self) -> u32;
+}
+
+/// Clear all bits of `data` beyond the first `prefix_len` ones.
+fn mask_prefix(data: &mut [u8], prefix_len: u32) {
+    for (i, byte) in data.iter_mut().enumerate() {
+        let bits = prefix_len.saturating_sub(i as u32 * 8).min(8);
+        *byte &= !(0xffu16 >> bits) as u8;
+    }
+}
+
+fn check_prefix_len(prefix_len: u32, max_prefix_len: u32) -> Result<()> {
+    if prefix_len > max_prefix_len {
+        return Err(Error::InvalidInput(format!(
+            "prefix length {prefix_len} exceeds maximum of {max_prefix_len}"
+        )));
+    }
+    Ok(())
+}
+
+/// Parse a prefix in CIDR notation ("addr/len") or a plain address, which is
+/// treated as a prefix covering all of its bits.
+fn parse_prefix<K>(s: &str) -> Result<K>
+where
+    K: LpmKey,
+    K::Addr: FromStr,
+{
+    let (addr, prefix_len) = match s.split_once('/') {
+        Some((addr, prefix_len)) => {
+            let prefix_len = prefix_len
+                .parse()
+                .map_err(|_| Error::InvalidInput(format!("invalid prefix length in `{s}`")))?;
+            (addr, prefix_len)
+        }
+        None => (s, K::MAX_PREFIX_LEN),
+    };
+
+    let addr = addr
+        .parse()
+        .map_err(|_| Error::InvalidInput(format!("invalid address in `{s}`")))?;
+    K::new(addr, prefix_len)
+}
+
+/// An [`LpmKey`] for IPv4 prefixes, for use with maps having 8 byte keys.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Ipv4LpmKey {
+    prefix_len: u32,
+    /// The address in network byte order.
+    data: [u8; 4],
+}
+
+// SAFETY: `Ipv4LpmKey` is `repr(C)`, has no padding, and every bit pattern is
+//         valid.
+unsafe impl Plain for Ipv4LpmKey {}
+
+// SAFETY: `Ipv4LpmKey` has no padding, see above.
+unsafe impl LpmKey for Ipv4LpmKey {
+    type Addr = Ipv4Addr;
+
+    const MAX_PREFIX_LEN: u32 = 32;
+
+    fn new(addr: Ipv4Addr, prefix_len: u32) -> Result<Self> {
+        let () = check_prefix_len(prefix_len, Self::MAX_PREFIX_LEN)?;
+        let mut data = addr.octets();
+        mask_prefix(&mut data, prefix_len);
+        Ok(Self { prefix_len, data })
+    }
+
+    fn addr(&self) -> Ipv4Addr {
+        Ipv4Addr::from(self.data)
+    }
+
+    fn prefix_len(&self) -> u32 {
+        self.prefix_len
+    }
+}
+
+impl FromStr for Ipv4LpmKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_prefix(s)
+    }
+}
+
+/// An [`LpmKey`] for IPv6 prefixes, for use with maps having 20 byte keys.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Ipv6LpmKey {
+    prefix_len: u32,
+    /// The address in network byte order.
+    data: [u8; 16],
+}
+
+// SAFETY: `Ipv6LpmKey` is `repr(C)`, has no padding, and every bit pattern is
+//         valid.
+unsafe impl Plain for Ipv6LpmKey {}
+
+// SAFETY: `Ipv6LpmKey` has no padding, see above.
+unsafe impl LpmKey for Ipv6LpmKey {
+    type Addr = Ipv6Addr;
+
+    const MAX_PREFIX_LEN: u32 = 128;
+
+    fn new(addr: Ipv6Addr, prefix_len: u32) -> Result<Self> {
+        let () = check_prefix_len(prefix_len, Self::MAX_PREFIX_LEN)?;
+        let mut data = addr.octets();
+        mask_prefix(&mut data, prefix_len);
+        Ok(Self { prefix_len, data })
+    }
+
+    fn addr(&self) -> Ipv6Addr {
+        Ipv6Addr::from(self.data)
+    }
+
+    fn prefix_len(&self) -> u32 {
+        self.prefix_len
+    }
+}
+
+impl FromStr for Ipv6LpmKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_prefix(s)
+    }
+}
+
+/// A view over a [`MapType::LpmTrie`] [`Map`] keyed by address prefixes.
+///
+/// Values are passed as raw bytes, just like for [`Map`] itself.
+#[derive(Debug)]
+pub struct LpmTrie<'m, K> {
+    map: &'m mut Map,
+    _phantom: PhantomData<K>,
+}
+
+impl<'m, K> LpmTrie<'m, K>
+where
+    K: LpmKey,
+{
+    /// Create a new `LpmTrie` wrapping the provided `Map`.
+    ///
+    /// Fails if `map` is not a [`MapType::LpmTrie`] or if its key size does
+    /// not match `K`.
+    pub fn new(map: &'m mut Map) -> Result<Self> {
+        if map.map_type() != MapType::LpmTrie {
+            return Err(Error::InvalidInput(format!(
+                "Invalid map type ({}) for LpmTrie",
+                map.map_type(),
+            )));
+        }
+
+        if map.key_size() as usize != size_of::<K>() {
+            return Err(Error::InvalidInput(format!(
+                "key_size {} != size of key type {}",
+                map.key_size(),
+                size_of::<K>()
+            )));
+        }
+
+        Ok(Self {
+            map,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Retrieve the underlying [`Map`].
+    pub fn map(&self) -> &Map {
+        self.map
+    }
+
+    /// Insert or update the value stored for the prefix described by `key`.
+    pub fn insert_prefix(&mut self, key: &K, value: &[u8], flags: MapFlags) -> Result<()> {
+        // SAFETY: `LpmKey` implementors are free of padding.
+        self.map.update(unsafe { pod_as_bytes(key) }, value, flags)
+    }
+
+    /// Remove the exact prefix described by `key`.
+    pub fn remove_prefix(&mut self, key: &K) -> Result<()> {
+        // SAFETY: `LpmKey` implementors are free of padding.
+        self.map.delete(unsafe { pod_as_bytes(key) })
+    }
+
+    /// Look up the value stored for the longest prefix matching `addr`, if
+    /// any.
+    pub fn lookup_longest_match(&self, addr: K::Addr) -> Result<Option<Vec<u8>>> {
+        let key = K::new(addr, K::MAX_PREFIX_LEN)?;
+        // SAFETY: `LpmKey` implementors are free of padding.
+        self.map
+            .lookup(unsafe { pod_as_bytes(&key) }, MapFlags::ANY)
+    }
+
+    /// Returns an iterator over all prefixes stored in this map.
+    ///
+    /// The same caveats as for [`Map::keys()`] apply.
+    pub fn prefixes(&self) -> impl Iterator<Item = K> + '_ {
+        self.map.keys().map(|key| pod_from_bytes(&key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ipv4_lpm_key_masking() {
+        let key = Ipv4LpmKey::new(Ipv4Addr::new(10, 1, 255, 3), 12).unwrap();
+        assert_eq!(key.addr(), Ipv4Addr::new(10, 0, 0, 0));
+        assert_eq!(key.prefix_len(), 12);
+
+        let key = Ipv4LpmKey::new(Ipv4Addr::new(10, 1, 255, 3), 32).unwrap();
+        assert_eq!(key.addr(), Ipv4Addr::new(10, 1, 255, 3));
+
+        let key = Ipv4LpmKey::new(Ipv4Addr::new(10, 1, 255, 3), 0).unwrap();
+        assert_eq!(key.addr(), Ipv4Addr::UNSPECIFIED);
+
+        assert!(Ipv4LpmKey::new(Ipv4Addr::LOCALHOST, 33).is_err());
+    }
+
+    #[test]
+    fn test_ipv4_lpm_key_layout() {
+        let key = Ipv4LpmKey::new(Ipv4Addr::new(192, 168, 0, 0), 16).unwrap();
+        let mut expected = 16u32.to_ne_bytes().to_vec();
+        expected.extend([192, 168, 0, 0]);
+        assert_eq!(unsafe { pod_as_bytes(&key) }, expected.as_slice());
+    }
+
+    #[test]
+    fn test_ipv4_lpm_key_parse() {
+        let key = "192.168.17.0/20".parse::<Ipv4LpmKey>().unwrap();
+        assert_eq!(key.addr(), Ipv4Addr::new(192, 168, 16, 0));
+        assert_eq!(key.prefix_len(), 20);
+
+        let key = "192.168.17.1".parse::<Ipv4LpmKey>().unwrap();
+        assert_eq!(key.addr(), Ipv4Addr::new(192, 168, 17, 1));
+        assert_eq!(key.prefix_len(), 32);
+
+        assert!("192.168.17.0/33".parse::<Ipv4LpmKey>().is_err());
+        assert!("192.168.17.0/".parse::<Ipv4LpmKey>().is_err());
+        assert!("192.168.17/8".parse::<Ipv4LpmKey>().is_err());
+        assert!("::1/8".parse::<Ipv4LpmKey>().is_err());
+    }
+
+    #[test]
+    fn test_ipv6_lpm_key_parse() {
+        let key = "2001:db8:ffff::1/36".parse::<Ipv6LpmKey>().unwrap();
+        assert_eq!(key.addr(), "2001:db8:f000::".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(key.prefix_len(), 36);
+
+        let key = "::1".parse::<Ipv6LpmKey>().unwrap();
+        assert_eq!(key.addr(), Ipv6Addr::LOCALHOST);
+        assert_eq!(key.prefix_len(), 128);
+
+        assert!("::1/129".parse::<Ipv6LpmKey>().is_err());
+        assert!("10.0.0.0/8".parse::<Ipv6LpmKey>().is_err());
+    }
+}
@@ -2,13 +2,15 @@ use core::ffi::c_void;
 use std::convert::TryFrom;
 use std::ffi::CStr;
 use std::fmt::Debug;
+use std::mem;
 use std::path::Path;
 use std::ptr;
 use std::ptr::null;
 
 use bitflags::bitflags;
-use nix::{errno, unistd};
+use nix::{errno, libc, unistd};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
+use plain::Plain;
 use strum_macros::Display;
 
 use crate::*;
@@ -95,6 +97,10 @@ impl OpenMap {
         util::parse_ret(ret)
     }
 
+    /// Use `inner` as the template for the inner maps of this map-in-map.
+    ///
+    /// `inner` has to be kept alive until the object this map belongs to has
+    /// been loaded.
     pub fn set_inner_map_fd(&mut self, inner: &Map) {
         unsafe { libbpf_sys::bpf_map__set_inner_map_fd(self.ptr, inner.fd()) };
     }
@@ -154,7 +160,8 @@ pub struct Map {
     key_size: u32,
     value_size: u32,
 
-    // The ptr will be null if we use Map::create to create the map from the userspace side directly.
+    // The ptr will be null if we use Map::create to create the map from the userspace side directly,
+    // or if the map was opened by pin path or id.
     ptr: *mut libbpf_sys::bpf_map,
 }
 
@@ -219,6 +226,18 @@ impl Map {
         Ok(ncpu * aligned_val_size)
     }
 
+    /// Split a buffer as filled in by a lookup of a per-cpu map into one
+    /// value per cpu.
+    fn split_percpu_values(&self, raw_vals: &[u8]) -> Vec<Vec<u8>> {
+        let val_size = self.value_size() as usize;
+        let aligned_val_size = self.percpu_aligned_value_size();
+
+        raw_vals
+            .chunks_exact(aligned_val_size)
+            .map(|chunk| chunk[..val_size].to_vec())
+            .collect()
+    }
+
     /// [Pin](https://facebookmicrosites.github.io/bpf/blog/2018/08/31/object-lifetime.html#bpffs)
     /// this map to bpffs.
     pub fn pin<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
@@ -231,7 +250,7 @@ impl Map {
             unsafe { libbpf_sys::bpf_map__pin(self.ptr, path_ptr) }
         };
 
-        util::parse_ret(ret)
+        util::parse_ret(ret).with_context(|| format!("pin map `{}`", self.name))
     }
 
     /// [Unpin](https://facebookmicrosites.github.io/bpf/blog/2018/08/31/object-lifetime.html#bpffs)
@@ -246,7 +265,7 @@ impl Map {
             let path_c = util::path_to_cstring(path)?;
             let path_ptr = path_c.as_ptr();
             let ret = unsafe { libbpf_sys::bpf_map__unpin(self.ptr, path_ptr) };
-            util::parse_ret(ret)
+            util::parse_ret(ret).with_context(|| format!("unpin map `{}`", self.name))
         }
     }
 
@@ -279,20 +298,10 @@ impl Map {
             )));
         }
 
-        let val_size = self.value_size() as usize;
-        let aligned_val_size = self.percpu_aligned_value_size();
         let out_size = self.percpu_buffer_size()?;
 
         let raw_res = self.lookup_raw(key, flags, out_size)?;
-        if let Some(raw_vals) = raw_res {
-            let mut out = Vec::new();
-            for chunk in raw_vals.chunks_exact(aligned_val_size) {
-                out.push(chunk[..val_size].to_vec());
-            }
-            Ok(Some(out))
-        } else {
-            Ok(None)
-        }
+        Ok(raw_res.map(|raw_vals| self.split_percpu_values(&raw_vals)))
     }
 
     /// Internal function to return a value from a map into a buffer of the given size.
@@ -326,7 +335,7 @@ impl Map {
             if errno::Errno::from_i32(errno) == errno::Errno::ENOENT {
                 Ok(None)
             } else {
-                Err(Error::System(errno))
+                Err(Error::System(errno).context(format!("lookup in map `{}`", self.name)))
             }
         }
     }
@@ -345,7 +354,7 @@ impl Map {
 
         let ret =
             unsafe { libbpf_sys::bpf_map_delete_elem(self.fd, key.as_ptr() as *const c_void) };
-        util::parse_ret(ret)
+        util::parse_ret(ret).with_context(|| format!("delete from map `{}`", self.name))
     }
 
     /// Same as [`Map::lookup()`] except this also deletes the key from the map.
@@ -383,7 +392,8 @@ impl Map {
             if errno::Errno::from_i32(errno) == errno::Errno::ENOENT {
                 Ok(None)
             } else {
-                Err(Error::System(errno))
+                Err(Error::System(errno)
+                    .context(format!("lookup and delete in map `{}`", self.name)))
             }
         }
     }
@@ -480,7 +490,128 @@ impl Map {
             )
         };
 
-        util::parse_ret(ret)
+        util::parse_ret(ret).with_context(|| format!("update map `{}`", self.name))
+    }
+
+    /// Push an element onto a [`MapType::Queue`] or [`MapType::Stack`], or
+    /// add it to a [`MapType::BloomFilter`].
+    ///
+    /// `value` must have exactly [`Map::value_size()`] elements. For queues and
+    /// stacks, [`MapFlags::EXIST`] can be used to replace the oldest element if
+    /// the map is full.
+    pub fn push(&mut self, value: &[u8], flags: MapFlags) -> Result<()> {
+        if !matches!(
+            self.map_type(),
+            MapType::Queue | MapType::Stack | MapType::BloomFilter
+        ) {
+            return Err(Error::InvalidInput(format!(
+                "Invalid map type ({}) for push()",
+                self.map_type(),
+            )));
+        }
+
+        let () = self.check_value_len(value)?;
+
+        let ret = unsafe {
+            libbpf_sys::bpf_map_update_elem(
+                self.fd,
+                ptr::null(),
+                value.as_ptr() as *const c_void,
+                flags.bits,
+            )
+        };
+        util::parse_ret(ret).with_context(|| format!("push to map `{}`", self.name))
+    }
+
+    /// Remove and return the element at the head of a [`MapType::Queue`] or
+    /// the top of a [`MapType::Stack`].
+    ///
+    /// Returns `None` if the map is empty.
+    pub fn pop(&mut self) -> Result<Option<Vec<u8>>> {
+        let () = self.check_queue_or_stack("pop")?;
+
+        let mut out = vec![0; self.value_size() as usize];
+        let ret = unsafe {
+            libbpf_sys::bpf_map_lookup_and_delete_elem(
+                self.fd,
+                ptr::null(),
+                out.as_mut_ptr() as *mut c_void,
+            )
+        };
+        Self::parse_lookup_ret(ret, out).with_context(|| format!("pop from map `{}`", self.name))
+    }
+
+    /// Return the element at the head of a [`MapType::Queue`] or the top of a
+    /// [`MapType::Stack`] without removing it.
+    ///
+    /// Returns `None` if the map is empty.
+    pub fn peek(&self) -> Result<Option<Vec<u8>>> {
+        let () = self.check_queue_or_stack("peek")?;
+
+        let mut out = vec![0; self.value_size() as usize];
+        let ret = unsafe {
+            libbpf_sys::bpf_map_lookup_elem(self.fd, ptr::null(), out.as_mut_ptr() as *mut c_void)
+        };
+        Self::parse_lookup_ret(ret, out).with_context(|| format!("peek map `{}`", self.name))
+    }
+
+    /// Check whether `value` may be contained in a [`MapType::BloomFilter`].
+    ///
+    /// As is inherent to bloom filters, `true` may be a false positive, while
+    /// `false` is always correct.
+    ///
+    /// `value` must have exactly [`Map::value_size()`] elements.
+    pub fn contains(&self, value: &[u8]) -> Result<bool> {
+        if self.map_type() != MapType::BloomFilter {
+            return Err(Error::InvalidInput(format!(
+                "Invalid map type ({}) for contains()",
+                self.map_type(),
+            )));
+        }
+
+        let () = self.check_value_len(value)?;
+
+        // The bloom filter reads the value to check for from the value
+        // pointer; it is not written to.
+        let ret = unsafe {
+            libbpf_sys::bpf_map_lookup_elem(self.fd, ptr::null(), value.as_ptr() as *mut c_void)
+        };
+        let found = Self::parse_lookup_ret(ret, ())
+            .with_context(|| format!("lookup in map `{}`", self.name))?;
+        Ok(found.is_some())
+    }
+
+    fn check_queue_or_stack(&self, op: &str) -> Result<()> {
+        if !matches!(self.map_type(), MapType::Queue | MapType::Stack) {
+            return Err(Error::InvalidInput(format!(
+                "Invalid map type ({}) for {op}()",
+                self.map_type(),
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_value_len(&self, value: &[u8]) -> Result<()> {
+        if value.len() != self.value_size() as usize {
+            return Err(Error::InvalidInput(format!(
+                "value_size {} != {}",
+                value.len(),
+                self.value_size()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Internal function to map the return value of a lookup to `Some(out)`
+    /// on success and `None` if no element was found.
+    fn parse_lookup_ret<T>(ret: i32, out: T) -> Result<Option<T>> {
+        if ret == 0 {
+            Ok(Some(out))
+        } else if errno::Errno::from_i32(-ret) == errno::Errno::ENOENT {
+            Ok(None)
+        } else {
+            Err(Error::System(-ret))
+        }
     }
 
     /// Freeze the map as read-only from user space.
@@ -492,7 +623,28 @@ impl Map {
     pub fn freeze(&self) -> Result<()> {
         let ret = unsafe { libbpf_sys::bpf_map_freeze(self.fd) };
 
-        util::parse_ret(ret)
+        util::parse_ret(ret).with_context(|| format!("freeze map `{}`", self.name))
+    }
+
+    /// Memory map the contents of this map for read-only access.
+    ///
+    /// Only [`MapType::Array`] maps created with the
+    /// [`BPF_F_MMAPABLE`][libbpf_sys::BPF_F_MMAPABLE] flag can be memory
+    /// mapped. Reading through the mapping does not require any system calls.
+    /// See [`MapMmap`] for how values can be accessed.
+    pub fn mmap(&self) -> Result<MapMmap<'_>> {
+        let region = MmapRegion::new(self, false)?;
+        Ok(MapMmap { region, _map: self })
+    }
+
+    /// Memory map the contents of this map for read-write access.
+    ///
+    /// The same restrictions as for [`Map::mmap()`] apply. In addition, maps
+    /// that have been frozen (see [`Map::freeze()`]) can only be mapped
+    /// read-only and this method fails for them.
+    pub fn mmap_mut(&mut self) -> Result<MapMmapMut<'_>> {
+        let region = MmapRegion::new(self, true)?;
+        Ok(MapMmapMut { region, _map: self })
     }
 
     /// Returns an iterator over keys in this map
@@ -504,7 +656,302 @@ impl Map {
         MapKeyIter::new(self, self.key_size())
     }
 
+    /// Returns an iterator over all `(key, value)` pairs in this map.
+    ///
+    /// If the kernel supports batched lookups for the map, elements are
+    /// retrieved in batches (see [`Map::lookup_batch()`]). Otherwise the
+    /// iterator walks the keys of the map and looks up each of them.
+    ///
+    /// Elements that get deleted after their key was retrieved but before
+    /// their value could be looked up are skipped. Beyond that, the same
+    /// caveats as for [`Map::keys()`] apply if the map is not stable during
+    /// iteration. An error encountered while retrieving elements is reported
+    /// as the last item of the iteration.
+    ///
+    /// For per-cpu maps, [`Map::entries_percpu()`] must be used.
+    pub fn entries(&self) -> Result<MapEntryIter<'_>> {
+        if self.map_type().is_percpu() {
+            return Err(Error::InvalidInput(format!(
+                "entries_percpu() must be used for per-cpu maps (type of the map is {})",
+                self.map_type(),
+            )));
+        }
+
+        MapEntryIter::new(self)
+    }
+
+    /// Same as [`Map::entries()`] except this reports one value per cpu, just
+    /// like [`Map::lookup_percpu()`].
+    ///
+    /// For normal maps, [`Map::entries()`] must be used.
+    pub fn entries_percpu(&self) -> Result<MapPercpuEntryIter<'_>> {
+        if !self.map_type().is_percpu() {
+            return Err(Error::InvalidInput(format!(
+                "entries() must be used for maps that are not per-cpu (type of the map is {})",
+                self.map_type(),
+            )));
+        }
+
+        let iter = MapEntryIter::new(self)?;
+        Ok(MapPercpuEntryIter { iter })
+    }
+
+    /// Returns an iterator over all elements in this map, retrieving them from
+    /// the kernel in batches of up to `batch_size` elements at a time.
+    ///
+    /// Each element is reported as a `(key, value)` pair. Compared to
+    /// iterating over [`Map::keys()`] and looking up each key individually,
+    /// this requires a single system call per batch instead of two per
+    /// element.
+    ///
+    /// The first batch is retrieved eagerly, meaning that errors such as the
+    /// kernel not supporting batched operations for the map type are reported
+    /// right away. An error encountered while retrieving a subsequent batch
+    /// is reported as the last item of the iteration, so that a failed dump
+    /// can be told apart from a complete one.
+    ///
+    /// For per-cpu maps, [`Map::lookup_batch_percpu()`] must be used.
+    pub fn lookup_batch(
+        &self,
+        batch_size: u32,
+        elem_flags: MapFlags,
+        flags: MapFlags,
+    ) -> Result<BatchedMapIter<'_>> {
+        if self.map_type().is_percpu() {
+            return Err(Error::InvalidInput(format!(
+                "lookup_batch_percpu() must be used for per-cpu maps (type of the map is {})",
+                self.map_type(),
+            )));
+        }
+
+        BatchedMapIter::new(self, false, batch_size, elem_flags, flags)
+    }
+
+    /// Same as [`Map::lookup_batch()`] except this reports one value per cpu,
+    /// just like [`Map::lookup_percpu()`].
+    ///
+    /// For normal maps, [`Map::lookup_batch()`] must be used.
+    pub fn lookup_batch_percpu(
+        &self,
+        batch_size: u32,
+        elem_flags: MapFlags,
+        flags: MapFlags,
+    ) -> Result<BatchedPercpuMapIter<'_>> {
+        if !self.map_type().is_percpu() {
+            return Err(Error::InvalidInput(format!(
+                "lookup_batch() must be used for maps that are not per-cpu (type of the map is {})",
+                self.map_type(),
+            )));
+        }
+
+        let iter = BatchedMapIter::new(self, false, batch_size, elem_flags, flags)?;
+        Ok(BatchedPercpuMapIter { iter })
+    }
+
+    /// Same as [`Map::lookup_batch()`] except this also deletes each batch of
+    /// elements from the map as it is retrieved.
+    ///
+    /// Elements the kernel deleted as part of a batch that failed may not be
+    /// reported, so callers should treat an error item as possible data loss.
+    pub fn lookup_and_delete_batch(
+        &mut self,
+        batch_size: u32,
+        elem_flags: MapFlags,
+        flags: MapFlags,
+    ) -> Result<BatchedMapIter<'_>> {
+        if self.map_type().is_percpu() {
+            return Err(Error::InvalidInput(format!(
+                "lookup_and_delete_batch_percpu() must be used for per-cpu maps (type of the map is {})",
+                self.map_type(),
+            )));
+        }
+
+        BatchedMapIter::new(self, true, batch_size, elem_flags, flags)
+    }
+
+    /// Same as [`Map::lookup_batch_percpu()`] except this also deletes each
+    /// batch of elements from the map as it is retrieved.
+    pub fn lookup_and_delete_batch_percpu(
+        &mut self,
+        batch_size: u32,
+        elem_flags: MapFlags,
+        flags: MapFlags,
+    ) -> Result<BatchedPercpuMapIter<'_>> {
+        if !self.map_type().is_percpu() {
+            return Err(Error::InvalidInput(format!(
+                "lookup_and_delete_batch() must be used for maps that are not per-cpu (type of the map is {})",
+                self.map_type(),
+            )));
+        }
+
+        let iter = BatchedMapIter::new(self, true, batch_size, elem_flags, flags)?;
+        Ok(BatchedPercpuMapIter { iter })
+    }
+
+    /// Update multiple elements with a single system call.
+    ///
+    /// `keys` must contain a whole number of keys of exactly
+    /// [`Map::key_size()`] bytes each, laid out back to back. `values` must
+    /// contain the same number of values of exactly [`Map::value_size()`]
+    /// bytes each.
+    ///
+    /// For per-cpu maps, [`Map::update_batch_percpu()`] must be used.
+    pub fn update_batch(
+        &mut self,
+        keys: &[u8],
+        values: &[u8],
+        elem_flags: MapFlags,
+        flags: MapFlags,
+    ) -> Result<()> {
+        if self.map_type().is_percpu() {
+            return Err(Error::InvalidInput(format!(
+                "update_batch_percpu() must be used for per-cpu maps (type of the map is {})",
+                self.map_type(),
+            )));
+        }
+
+        let mut count = self.batch_count(keys)?;
+        if values.len() != count as usize * self.value_size() as usize {
+            return Err(Error::InvalidInput(format!(
+                "values size {} != {} * {}",
+                values.len(),
+                count,
+                self.value_size()
+            )));
+        }
+
+        let opts = batch_opts(elem_flags, flags);
+        let ret = unsafe {
+            libbpf_sys::bpf_map_update_batch(
+                self.fd,
+                keys.as_ptr() as *const c_void,
+                values.as_ptr() as *const c_void,
+                &mut count,
+                &opts,
+            )
+        };
+        util::parse_ret(ret).with_context(|| format!("batch update map `{}`", self.name))
+    }
+
+    /// Same as [`Map::update_batch()`] except this takes one value per cpu for
+    /// each key, just like [`Map::update_percpu()`].
+    ///
+    /// `values` must contain one element per key in `keys`, each of which has
+    /// one value per cpu (see [`num_possible_cpus()`]) of exactly
+    /// [`Map::value_size()`] bytes.
+    ///
+    /// For normal maps, [`Map::update_batch()`] must be used.
+    pub fn update_batch_percpu(
+        &mut self,
+        keys: &[u8],
+        values: &[Vec<Vec<u8>>],
+        elem_flags: MapFlags,
+        flags: MapFlags,
+    ) -> Result<()> {
+        if !self.map_type().is_percpu() {
+            return Err(Error::InvalidInput(format!(
+                "update_batch() must be used for maps that are not per-cpu (type of the map is {})",
+                self.map_type(),
+            )));
+        }
+
+        let mut count = self.batch_count(keys)?;
+        if values.len() != count as usize {
+            return Err(Error::InvalidInput(format!(
+                "number of values {} != number of keys {}",
+                values.len(),
+                count
+            )));
+        }
+
+        let ncpu = num_possible_cpus()?;
+        let val_size = self.value_size() as usize;
+        let aligned_val_size = self.percpu_aligned_value_size();
+        let buf_size = self.percpu_buffer_size()?;
+
+        let mut value_buf = vec![0; values.len() * buf_size];
+
+        for (k, percpu_vals) in values.iter().enumerate() {
+            if percpu_vals.len() != ncpu {
+                return Err(Error::InvalidInput(format!(
+                    "number of values {} for key {} != number of cpus {}",
+                    percpu_vals.len(),
+                    k,
+                    ncpu
+                )));
+            }
+
+            for (i, val) in percpu_vals.iter().enumerate() {
+                if val.len() != val_size {
+                    return Err(Error::InvalidInput(format!(
+                        "value size for key {} on cpu {} is {} != {}",
+                        k,
+                        i,
+                        val.len(),
+                        val_size
+                    )));
+                }
+
+                let off = k * buf_size + i * aligned_val_size;
+                value_buf[off..off + val_size].copy_from_slice(val);
+            }
+        }
+
+        let opts = batch_opts(elem_flags, flags);
+        let ret = unsafe {
+            libbpf_sys::bpf_map_update_batch(
+                self.fd,
+                keys.as_ptr() as *const c_void,
+                value_buf.as_ptr() as *const c_void,
+                &mut count,
+                &opts,
+            )
+        };
+        util::parse_ret(ret).with_context(|| format!("batch update map `{}`", self.name))
+    }
+
+    /// Delete multiple elements with a single system call.
+    ///
+    /// `keys` must contain a whole number of keys of exactly
+    /// [`Map::key_size()`] bytes each, laid out back to back.
+    pub fn delete_batch(
+        &mut self,
+        keys: &[u8],
+        elem_flags: MapFlags,
+        flags: MapFlags,
+    ) -> Result<()> {
+        let mut count = self.batch_count(keys)?;
+        let opts = batch_opts(elem_flags, flags);
+        let ret = unsafe {
+            libbpf_sys::bpf_map_delete_batch(
+                self.fd,
+                keys.as_ptr() as *const c_void,
+                &mut count,
+                &opts,
+            )
+        };
+        util::parse_ret(ret).with_context(|| format!("batch delete from map `{}`", self.name))
+    }
+
+    /// Internal function to determine the number of keys in a buffer of
+    /// back to back keys.
+    fn batch_count(&self, keys: &[u8]) -> Result<u32> {
+        let key_size = self.key_size() as usize;
+        if key_size == 0 || keys.len() % key_size != 0 {
+            return Err(Error::InvalidInput(format!(
+                "keys size {} is not a multiple of key_size {}",
+                keys.len(),
+                key_size
+            )));
+        }
+
+        u32::try_from(keys.len() / key_size)
+            .map_err(|_| Error::InvalidInput("too many keys in batch".to_string()))
+    }
+
     /// Create the bpf map standalone.
+    ///
+    /// The map's file descriptor is closed once the returned `Map` is dropped.
     pub fn create<T: AsRef<str>>(
         map_type: MapType,
         name: Option<T>,
@@ -557,6 +1004,142 @@ impl Map {
         })
     }
 
+    /// Internal function to create a `Map` taking ownership of the map
+    /// referred to by `fd`, retrieving its attributes from the kernel.
+    ///
+    /// `fd` is closed if this function fails.
+    pub(crate) fn from_owned_fd(fd: i32) -> Result<Map> {
+        let info = match map_info_by_fd(fd) {
+            Ok(info) => info,
+            Err(err) => {
+                let _ = unistd::close(fd);
+                return Err(err);
+            }
+        };
+
+        Ok(Map {
+            fd,
+            name: query::name_arr_to_string(&info.name, ""),
+            ty: info.type_,
+            key_size: info.key_size,
+            value_size: info.value_size,
+            ptr: ptr::null_mut(),
+        })
+    }
+
+    /// Open the map [pinned](https://facebookmicrosites.github.io/bpf/blog/2018/08/31/object-lifetime.html#bpffs)
+    /// at `path`.
+    ///
+    /// The map's name, type, key and value sizes are retrieved from the
+    /// kernel. The returned `Map` owns its file descriptor, which is closed
+    /// once it is dropped.
+    pub fn from_pinned_path<P: AsRef<Path>>(path: P) -> Result<Map> {
+        let fd = util::bpf_obj_get(path.as_ref(), util::BpfObjKind::Map)?;
+        Self::from_owned_fd(fd)
+    }
+
+    /// Open the map with the kernel assigned ID `id`.
+    ///
+    /// The map's name, type, key and value sizes are retrieved from the
+    /// kernel. The returned `Map` owns its file descriptor, which is closed
+    /// once it is dropped.
+    pub fn from_id(id: u32) -> Result<Map> {
+        let fd = unsafe { libbpf_sys::bpf_map_get_fd_by_id(id) };
+        if fd < 0 {
+            return Err(Error::System(-fd).context(format!("open map with id {id}")));
+        }
+
+        Self::from_owned_fd(fd)
+    }
+
+    /// Create a new map suitable for insertion into this map-in-map, based on
+    /// the inner map definition this map was declared with in the BPF object.
+    ///
+    /// Only available for [`MapType::ArrayOfMaps`] and [`MapType::HashOfMaps`]
+    /// maps originating from an [`Object`]; for maps created with
+    /// [`Map::create()`] the kernel does not report the inner map definition.
+    pub fn create_inner_map<T: AsRef<str>>(&self, name: Option<T>) -> Result<Map> {
+        let () = self.check_map_in_map("create_inner_map")?;
+
+        let inner = if self.ptr.is_null() {
+            ptr::null_mut()
+        } else {
+            unsafe { libbpf_sys::bpf_map__inner_map(self.ptr) }
+        };
+        if inner.is_null() {
+            return Err(Error::InvalidInput(format!(
+                "no inner map definition available for map {}",
+                self.name(),
+            )));
+        }
+
+        let map_type = unsafe { libbpf_sys::bpf_map__type(inner) };
+        let map_type = MapType::try_from(map_type).unwrap_or(MapType::Unknown);
+        let key_size = unsafe { libbpf_sys::bpf_map__key_size(inner) };
+        let value_size = unsafe { libbpf_sys::bpf_map__value_size(inner) };
+        let max_entries = unsafe { libbpf_sys::bpf_map__max_entries(inner) };
+        let opts = libbpf_sys::bpf_map_create_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_map_create_opts>() as libbpf_sys::size_t,
+            map_flags: unsafe { libbpf_sys::bpf_map__map_flags(inner) },
+            map_extra: unsafe { libbpf_sys::bpf_map__map_extra(inner) },
+            numa_node: unsafe { libbpf_sys::bpf_map__numa_node(inner) },
+            ..Default::default()
+        };
+
+        Map::create(map_type, name, key_size, value_size, max_entries, &opts)
+    }
+
+    /// Insert `inner` into this map-in-map at `key`, replacing any map
+    /// present there already (depending on `flags`).
+    ///
+    /// `key` must have exactly [`Map::key_size()`] elements.
+    pub fn update_inner_map(&mut self, key: &[u8], inner: &Map, flags: MapFlags) -> Result<()> {
+        let () = self.check_map_in_map("update_inner_map")?;
+        self.update(key, &inner.fd().to_ne_bytes(), flags)
+    }
+
+    /// Retrieve the map stored in this map-in-map at `key`.
+    ///
+    /// The returned `Map` is a new, owned handle to the inner map, which stays
+    /// valid even if the map is removed from this map in the meantime.
+    ///
+    /// `key` must have exactly [`Map::key_size()`] elements.
+    pub fn lookup_inner_map(&self, key: &[u8]) -> Result<Option<Map>> {
+        let () = self.check_map_in_map("lookup_inner_map")?;
+
+        let id = match self.lookup(key, MapFlags::ANY)? {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        let id =
+            u32::from_ne_bytes(id.as_slice().try_into().map_err(|_| {
+                Error::Internal(format!("unexpected inner map id size {}", id.len()))
+            })?);
+
+        let fd = unsafe { libbpf_sys::bpf_map_get_fd_by_id(id) };
+        if fd < 0 {
+            let err = -fd;
+            // The inner map may have been removed and released since we
+            // retrieved its id.
+            if errno::Errno::from_i32(err) == errno::Errno::ENOENT {
+                return Ok(None);
+            }
+            return Err(Error::System(err));
+        }
+
+        Map::from_owned_fd(fd).map(Some)
+    }
+
+    fn check_map_in_map(&self, op: &str) -> Result<()> {
+        if !matches!(self.map_type(), MapType::ArrayOfMaps | MapType::HashOfMaps) {
+            return Err(Error::InvalidInput(format!(
+                "Invalid map type ({}) for {op}()",
+                self.map_type(),
+            )));
+        }
+        Ok(())
+    }
+
     /// Attach a struct ops map
     pub fn attach_struct_ops(&mut self) -> Result<Link> {
         if self.map_type() != MapType::StructOps {
@@ -584,6 +1167,38 @@ impl Map {
     }
 }
 
+/// Retrieve information about the map referred to by `fd` from the kernel.
+pub(crate) fn map_info_by_fd(fd: i32) -> Result<libbpf_sys::bpf_map_info> {
+    // Padding bytes need to be zero as well, so use `zeroed()` instead of
+    // `default()`. See `query` for details.
+    let mut info: libbpf_sys::bpf_map_info = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libbpf_sys::bpf_map_info>() as u32;
+    let ret = unsafe {
+        libbpf_sys::bpf_obj_get_info_by_fd(fd, &mut info as *mut _ as *mut c_void, &mut len)
+    };
+    util::parse_ret(ret)?;
+    Ok(info)
+}
+
+/// Create the options for a batched map operation.
+fn batch_opts(elem_flags: MapFlags, flags: MapFlags) -> libbpf_sys::bpf_map_batch_opts {
+    libbpf_sys::bpf_map_batch_opts {
+        sz: mem::size_of::<libbpf_sys::bpf_map_batch_opts>() as libbpf_sys::size_t,
+        elem_flags: elem_flags.bits,
+        flags: flags.bits,
+    }
+}
+
+impl Drop for Map {
+    fn drop(&mut self) {
+        // Maps belonging to an `Object` are closed along with it. All others
+        // are owned by us.
+        if self.ptr.is_null() {
+            let _ = unistd::close(self.fd);
+        }
+    }
+}
+
 bitflags! {
     /// Flags to configure [`Map`] operations.
     pub struct MapFlags: u64 {
@@ -637,6 +1252,7 @@ pub enum MapType {
     InodeStorage,
     TaskStorage,
     BloomFilter,
+    UserRingBuf,
     /// We choose to specify our own "unknown" type here b/c it's really up to the kernel
     /// to decide if it wants to reject the map. If it accepts it, it just means whoever
     /// using this library is a bit out of date.
@@ -703,3 +1319,558 @@ impl<'a> Iterator for MapKeyIter<'a> {
         }
     }
 }
+
+/// An iterator over the elements of a [`Map`], retrieving them in batches.
+///
+/// Created by [`Map::lookup_batch()`] and [`Map::lookup_and_delete_batch()`].
+#[derive(Debug)]
+pub struct BatchedMapIter<'a> {
+    map: &'a Map,
+    delete: bool,
+    batch_size: u32,
+    opts: libbpf_sys::bpf_map_batch_opts,
+    /// Opaque cursor to pass to the next batch operation; `None` before the
+    /// first batch.
+    in_batch: Option<Vec<u8>>,
+    /// Opaque cursor filled in by a batch operation.
+    out_batch: Vec<u8>,
+    /// Size of one value as laid out by the kernel.
+    value_stride: usize,
+    keys: Vec<u8>,
+    values: Vec<u8>,
+    /// Number of elements in the current batch.
+    len: usize,
+    /// Index of the next element of the current batch to report.
+    index: usize,
+    /// Whether the kernel signaled that there are no more elements.
+    done: bool,
+}
+
+impl<'a> BatchedMapIter<'a> {
+    fn new(
+        map: &'a Map,
+        delete: bool,
+        batch_size: u32,
+        elem_flags: MapFlags,
+        flags: MapFlags,
+    ) -> Result<Self> {
+        if batch_size == 0 {
+            return Err(Error::InvalidInput(
+                "batch_size must be greater than zero".to_string(),
+            ));
+        }
+
+        let value_stride = if map.map_type().is_percpu() {
+            map.percpu_buffer_size()?
+        } else {
+            map.value_size() as usize
+        };
+
+        let mut iter = Self {
+            map,
+            delete,
+            batch_size,
+            opts: batch_opts(elem_flags, flags),
+            in_batch: None,
+            // Depending on the map type, the kernel uses either a key or a
+            // 32 bit bucket index as the cursor.
+            out_batch: vec![0; (map.key_size() as usize).max(mem::size_of::<u32>())],
+            value_stride,
+            keys: Vec::new(),
+            values: Vec::new(),
+            len: 0,
+            index: 0,
+            done: false,
+        };
+
+        let () = iter.fetch()?;
+        Ok(iter)
+    }
+
+    /// Retrieve the next batch of elements from the kernel.
+    fn fetch(&mut self) -> Result<()> {
+        loop {
+            let batch_size = self.batch_size as usize;
+            self.keys
+                .resize(batch_size * self.map.key_size() as usize, 0);
+            self.values.resize(batch_size * self.value_stride, 0);
+
+            let in_batch = self
+                .in_batch
+                .as_mut()
+                .map_or(ptr::null_mut(), |b| b.as_mut_ptr());
+            let mut count = self.batch_size;
+            let ret = unsafe {
+                if self.delete {
+                    libbpf_sys::bpf_map_lookup_and_delete_batch(
+                        self.map.fd(),
+                        in_batch as *mut c_void,
+                        self.out_batch.as_mut_ptr() as *mut c_void,
+                        self.keys.as_mut_ptr() as *mut c_void,
+                        self.values.as_mut_ptr() as *mut c_void,
+                        &mut count,
+                        &self.opts,
+                    )
+                } else {
+                    libbpf_sys::bpf_map_lookup_batch(
+                        self.map.fd(),
+                        in_batch as *mut c_void,
+                        self.out_batch.as_mut_ptr() as *mut c_void,
+                        self.keys.as_mut_ptr() as *mut c_void,
+                        self.values.as_mut_ptr() as *mut c_void,
+                        &mut count,
+                        &self.opts,
+                    )
+                }
+            };
+
+            if ret < 0 {
+                match errno::Errno::from_i32(-ret) {
+                    // We reached the end of the map; `count` still reflects
+                    // the elements retrieved as part of this last batch.
+                    errno::Errno::ENOENT => self.done = true,
+                    // A hash bucket holds more elements than fit into a
+                    // batch. Retry with a larger batch.
+                    errno::Errno::ENOSPC if count == 0 => {
+                        self.batch_size = self.batch_size.saturating_mul(2);
+                        continue;
+                    }
+                    _ => {
+                        let op = if self.delete {
+                            "batch lookup and delete in"
+                        } else {
+                            "batch lookup in"
+                        };
+                        return Err(
+                            Error::System(-ret).context(format!("{} map `{}`", op, self.map.name))
+                        );
+                    }
+                }
+            } else if count == 0 {
+                self.done = true;
+            }
+
+            self.len = count as usize;
+            self.index = 0;
+            self.in_batch = Some(self.out_batch.clone());
+            return Ok(());
+        }
+    }
+
+    /// Report the next element as a key and the raw value buffer.
+    fn next_raw(&mut self) -> Option<Result<(Vec<u8>, &[u8])>> {
+        while self.index >= self.len {
+            if self.done {
+                return None;
+            }
+            if let Err(err) = self.fetch() {
+                // Report the error once and end iteration after that.
+                self.done = true;
+                return Some(Err(err));
+            }
+        }
+
+        let key_size = self.map.key_size() as usize;
+        let i = self.index;
+        self.index += 1;
+
+        let key = self.keys[i * key_size..(i + 1) * key_size].to_vec();
+        let value = &self.values[i * self.value_stride..(i + 1) * self.value_stride];
+        Some(Ok((key, value)))
+    }
+}
+
+impl<'a> Iterator for BatchedMapIter<'a> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_raw()
+            .map(|result| result.map(|(key, value)| (key, value.to_vec())))
+    }
+}
+
+/// An iterator over the elements of a per-cpu [`Map`], retrieving them in
+/// batches.
+///
+/// Created by [`Map::lookup_batch_percpu()`] and
+/// [`Map::lookup_and_delete_batch_percpu()`].
+#[derive(Debug)]
+pub struct BatchedPercpuMapIter<'a> {
+    iter: BatchedMapIter<'a>,
+}
+
+impl<'a> Iterator for BatchedPercpuMapIter<'a> {
+    type Item = Result<(Vec<u8>, Vec<Vec<u8>>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let map = self.iter.map;
+        self.iter
+            .next_raw()
+            .map(|result| result.map(|(key, raw_vals)| (key, map.split_percpu_values(raw_vals))))
+    }
+}
+
+/// Number of elements to retrieve at a time when iterating over the entries
+/// of a map with batched lookups.
+const ENTRY_BATCH_SIZE: u32 = 64;
+
+/// The kernel internal `ENOTSUPP`, reported by some kernels for map types not
+/// supporting batched operations.
+const ENOTSUPP: i32 = 524;
+
+#[derive(Debug)]
+enum MapEntryIterImpl<'a> {
+    Batched(BatchedMapIter<'a>),
+    Keys {
+        keys: MapKeyIter<'a>,
+        value_size: usize,
+        /// Whether an error ended iteration.
+        done: bool,
+    },
+}
+
+/// An iterator over the `(key, value)` pairs of a [`Map`].
+///
+/// Created by [`Map::entries()`].
+#[derive(Debug)]
+pub struct MapEntryIter<'a> {
+    map: &'a Map,
+    imp: MapEntryIterImpl<'a>,
+}
+
+impl<'a> MapEntryIter<'a> {
+    fn new(map: &'a Map) -> Result<Self> {
+        let imp =
+            match BatchedMapIter::new(map, false, ENTRY_BATCH_SIZE, MapFlags::ANY, MapFlags::ANY) {
+                Ok(iter) => MapEntryIterImpl::Batched(iter),
+                // Batched lookups are not supported by all kernels and map
+                // types. Fall back to walking the keys of the map.
+                Err(err)
+                    if matches!(
+                        err.errno(),
+                        Some(libc::EINVAL) | Some(libc::EOPNOTSUPP) | Some(ENOTSUPP)
+                    ) =>
+                {
+                    let value_size = if map.map_type().is_percpu() {
+                        map.percpu_buffer_size()?
+                    } else {
+                        map.value_size() as usize
+                    };
+
+                    MapEntryIterImpl::Keys {
+                        keys: map.keys(),
+                        value_size,
+                        done: false,
+                    }
+                }
+                Err(err) => return Err(err),
+            };
+
+        Ok(Self { map, imp })
+    }
+
+    /// Report the next element as a key and the raw value buffer.
+    fn next_raw(&mut self) -> Option<Result<(Vec<u8>, Vec<u8>)>> {
+        match &mut self.imp {
+            MapEntryIterImpl::Batched(iter) => iter
+                .next_raw()
+                .map(|result| result.map(|(k, v)| (k, v.to_vec()))),
+            MapEntryIterImpl::Keys {
+                keys,
+                value_size,
+                done,
+            } => loop {
+                if *done {
+                    return None;
+                }
+
+                let key = keys.next()?;
+                match self.map.lookup_raw(&key, MapFlags::ANY, *value_size) {
+                    Ok(Some(value)) => return Some(Ok((key, value))),
+                    // The element got deleted since we retrieved its key.
+                    Ok(None) => continue,
+                    Err(err) => {
+                        // Report the error once and end iteration after that.
+                        *done = true;
+                        return Some(Err(err));
+                    }
+                }
+            },
+        }
+    }
+}
+
+impl<'a> Iterator for MapEntryIter<'a> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_raw()
+    }
+}
+
+/// An iterator over the `(key, values)` pairs of a per-cpu [`Map`].
+///
+/// Created by [`Map::entries_percpu()`].
+#[derive(Debug)]
+pub struct MapPercpuEntryIter<'a> {
+    iter: MapEntryIter<'a>,
+}
+
+impl<'a> Iterator for MapPercpuEntryIter<'a> {
+    type Item = Result<(Vec<u8>, Vec<Vec<u8>>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let map = self.iter.map;
+        self.iter
+            .next_raw()
+            .map(|result| result.map(|(key, raw_vals)| (key, map.split_percpu_values(&raw_vals))))
+    }
+}
+
+/// A memory mapped region backing the values of a [`Map`].
+#[derive(Debug)]
+struct MmapRegion {
+    ptr: *mut c_void,
+    /// Length of the mapping, rounded up to a multiple of the page size.
+    mmap_len: usize,
+    /// Length of the part of the mapping holding values.
+    data_len: usize,
+    /// Distance between two consecutive values.
+    value_stride: usize,
+    value_size: usize,
+}
+
+impl MmapRegion {
+    fn new(map: &Map, writable: bool) -> Result<Self> {
+        if map.map_type() != MapType::Array {
+            return Err(Error::InvalidInput(format!(
+                "Invalid map type ({}) for mmap()",
+                map.map_type(),
+            )));
+        }
+
+        let info = map_info_by_fd(map.fd())?;
+        if info.map_flags & libbpf_sys::BPF_F_MMAPABLE == 0 {
+            return Err(Error::InvalidInput(
+                "map was not created with BPF_F_MMAPABLE".to_string(),
+            ));
+        }
+
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        // Array map values are laid out with 8 byte alignment.
+        let value_stride = util::roundup(info.value_size as usize, 8);
+        let data_len = value_stride * info.max_entries as usize;
+        let mmap_len = util::roundup(data_len, page_size);
+
+        let prot = if writable {
+            libc::PROT_READ | libc::PROT_WRITE
+        } else {
+            libc::PROT_READ
+        };
+
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                mmap_len,
+                prot,
+                libc::MAP_SHARED,
+                map.fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(Error::System(errno::errno()).context(format!("mmap map `{}`", map.name)));
+        }
+
+        Ok(Self {
+            ptr,
+            mmap_len,
+            data_len,
+            value_stride,
+            value_size: info.value_size as usize,
+        })
+    }
+
+    /// Retrieve a pointer to the value at `index`.
+    fn value_ptr(&self, index: u32) -> Result<*mut u8> {
+        let start = index as usize * self.value_stride;
+        if start + self.value_size > self.data_len {
+            return Err(Error::InvalidInput(format!(
+                "index {} out of bounds for map with {} values",
+                index,
+                self.data_len / self.value_stride
+            )));
+        }
+        Ok(unsafe { (self.ptr as *mut u8).add(start) })
+    }
+
+    /// Retrieve a properly aligned pointer to the value at `index`, viewed as
+    /// a `T`.
+    fn typed_value_ptr<T>(&self, index: u32) -> Result<*mut T> {
+        if mem::size_of::<T>() != self.value_size {
+            return Err(Error::InvalidInput(format!(
+                "size of value type {} != value_size {}",
+                mem::size_of::<T>(),
+                self.value_size,
+            )));
+        }
+
+        let ptr = self.value_ptr(index)?;
+        if ptr as usize % mem::align_of::<T>() != 0 {
+            return Err(Error::InvalidInput(format!(
+                "value at index {} is not sufficiently aligned for a type with alignment {}",
+                index,
+                mem::align_of::<T>(),
+            )));
+        }
+        Ok(ptr as *mut T)
+    }
+
+    fn read_value(&self, index: u32) -> Result<Vec<u8>> {
+        let ptr = self.value_ptr(index)?;
+        // BPF programs may modify the value while we are reading it, so don't
+        // let the compiler assume anything about the memory.
+        Ok((0..self.value_size)
+            .map(|i| unsafe { ptr::read_volatile(ptr.add(i)) })
+            .collect())
+    }
+
+    fn read<T: Plain + Copy>(&self, index: u32) -> Result<T> {
+        let ptr = self.typed_value_ptr::<T>(index)?;
+        Ok(unsafe { ptr::read_volatile(ptr) })
+    }
+
+    fn write_value(&mut self, index: u32, value: &[u8]) -> Result<()> {
+        if value.len() != self.value_size {
+            return Err(Error::InvalidInput(format!(
+                "value size {} != value_size {}",
+                value.len(),
+                self.value_size
+            )));
+        }
+
+        let ptr = self.value_ptr(index)?;
+        for (i, byte) in value.iter().enumerate() {
+            unsafe { ptr::write_volatile(ptr.add(i), *byte) };
+        }
+        Ok(())
+    }
+
+    fn write<T: Plain + Copy>(&mut self, index: u32, value: T) -> Result<()> {
+        let ptr = self.typed_value_ptr::<T>(index)?;
+        unsafe { ptr::write_volatile(ptr, value) };
+        Ok(())
+    }
+}
+
+impl Drop for MmapRegion {
+    fn drop(&mut self) {
+        let _ = unsafe { libc::munmap(self.ptr, self.mmap_len) };
+    }
+}
+
+/// A read-only memory mapping of the values of a [`Map`].
+///
+/// Created by [`Map::mmap()`]. The values of the map are laid out back to
+/// back, with each value padded to a multiple of 8 bytes. The mapping is
+/// removed when this object is dropped.
+///
+/// Because BPF programs may modify the mapped memory at any time, no
+/// references into it are handed out. Values are instead copied out with
+/// volatile reads, or the memory can be accessed through the raw pointer
+/// returned by [`MapMmap::as_ptr()`].
+#[derive(Debug)]
+pub struct MapMmap<'a> {
+    region: MmapRegion,
+    _map: &'a Map,
+}
+
+impl MapMmap<'_> {
+    /// Retrieve a pointer to the start of the mapping.
+    ///
+    /// The pointer is valid for reads of [`MapMmap::len()`] bytes for as
+    /// long as this object is alive.
+    pub fn as_ptr(&self) -> *const u8 {
+        self.region.ptr as *const u8
+    }
+
+    /// Retrieve the length of the mapped values in bytes, including padding.
+    pub fn len(&self) -> usize {
+        self.region.data_len
+    }
+
+    /// Check whether the mapping is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copy out the raw bytes of the value at `index`, without padding.
+    pub fn value(&self, index: u32) -> Result<Vec<u8>> {
+        self.region.read_value(index)
+    }
+
+    /// Read the value at `index` as a `T`.
+    ///
+    /// The size of `T` has to match the value size of the map.
+    pub fn read<T: Plain + Copy>(&self, index: u32) -> Result<T> {
+        self.region.read(index)
+    }
+}
+
+/// A read-write memory mapping of the values of a [`Map`].
+///
+/// Created by [`Map::mmap_mut()`]. Apart from being writable, this behaves
+/// just like [`MapMmap`].
+#[derive(Debug)]
+pub struct MapMmapMut<'a> {
+    region: MmapRegion,
+    _map: &'a mut Map,
+}
+
+impl MapMmapMut<'_> {
+    /// See [`MapMmap::as_ptr()`].
+    pub fn as_ptr(&self) -> *const u8 {
+        self.region.ptr as *const u8
+    }
+
+    /// Retrieve a pointer to the start of the mapping.
+    ///
+    /// The pointer is valid for reads and writes of [`MapMmapMut::len()`]
+    /// bytes for as long as this object is alive.
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.region.ptr as *mut u8
+    }
+
+    /// See [`MapMmap::len()`].
+    pub fn len(&self) -> usize {
+        self.region.data_len
+    }
+
+    /// See [`MapMmap::is_empty()`].
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// See [`MapMmap::value()`].
+    pub fn value(&self, index: u32) -> Result<Vec<u8>> {
+        self.region.read_value(index)
+    }
+
+    /// See [`MapMmap::read()`].
+    pub fn read<T: Plain + Copy>(&self, index: u32) -> Result<T> {
+        self.region.read(index)
+    }
+
+    /// Overwrite the value at `index` with `value`, which must have exactly
+    /// [`Map::value_size()`] bytes.
+    pub fn set_value(&mut self, index: u32, value: &[u8]) -> Result<()> {
+        self.region.write_value(index, value)
+    }
+
+    /// Overwrite the value at `index` with `value`.
+    ///
+    /// The size of `T` has to match the value size of the map.
+    pub fn write<T: Plain + Copy>(&mut self, index: u32, value: T) -> Result<()> {
+        self.region.write(index, value)
+    }
+}
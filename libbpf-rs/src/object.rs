@@ -8,6 +8,8 @@ use crate::{util, *};
 pub struct ObjectBuilder {
     name: String,
     relaxed_maps: bool,
+    log_level: u32,
+    log_size: Option<usize>,
 }
 
 impl ObjectBuilder {
@@ -23,6 +25,24 @@ impl ObjectBuilder {
         self
     }
 
+    /// Set the verifier log level used when loading the programs of opened
+    /// objects.
+    ///
+    /// See [`OpenProgram::set_log_level()`].
+    pub fn log_level(&mut self, log_level: u32) -> &mut Self {
+        self.log_level = log_level;
+        self
+    }
+
+    /// Set the size of the buffer capturing the verifier log when loading the
+    /// programs of opened objects.
+    ///
+    /// See [`OpenProgram::set_log_size()`].
+    pub fn log_size(&mut self, log_size: usize) -> &mut Self {
+        self.log_size = Some(log_size);
+        self
+    }
+
     /// Option to print debug output to stderr.
     ///
     /// Note: This function uses [`set_print`] internally and will overwrite any callbacks
@@ -79,7 +99,8 @@ impl ObjectBuilder {
             return Err(Error::System(err as i32));
         }
 
-        OpenObject::new(obj)
+        let obj = OpenObject::new(obj)?;
+        self.configure_progs(obj)
     }
 
     /// Open an object from memory.
@@ -108,7 +129,22 @@ impl ObjectBuilder {
             return Err(Error::System(err as i32));
         }
 
-        OpenObject::new(obj)
+        let obj = OpenObject::new(obj)?;
+        self.configure_progs(obj)
+    }
+
+    /// Apply the per-program options of this builder to all programs of
+    /// `obj`.
+    fn configure_progs(&self, mut obj: OpenObject) -> Result<OpenObject> {
+        for prog in obj.progs_iter_mut() {
+            if self.log_level != 0 {
+                let () = prog.set_log_level(self.log_level)?;
+            }
+            if let Some(log_size) = self.log_size {
+                prog.set_log_size(log_size);
+            }
+        }
+        Ok(obj)
     }
 }
 
@@ -256,11 +292,35 @@ impl OpenObject {
     }
 
     /// Load the maps and programs contained in this BPF object into the system.
+    ///
+    /// If the kernel rejects a program whose verifier log was requested (see
+    /// [`OpenProgram::set_log_level()`] and [`OpenProgram::set_log_size()`]),
+    /// the returned error is an [`Error::ProgramLoad`] carrying the log.
     pub fn load(mut self) -> Result<Object> {
+        for prog in self.progs.values_mut() {
+            let () = prog.prepare_log_buf()?;
+        }
+
         let ret = unsafe { libbpf_sys::bpf_object__load(self.ptr) };
         if ret != 0 {
             // bpf_object__load() returns errno as negative, so flip
-            return Err(Error::System(-ret));
+            let errno = -ret;
+            // Loading stops at the first program that fails, which is the
+            // only one without a file descriptor that has a log. Failures
+            // unrelated to programs leave all logs empty.
+            let err = self
+                .progs
+                .iter()
+                .filter(|(_, prog)| unsafe { libbpf_sys::bpf_program__fd(prog.ptr) } < 0)
+                .find_map(|(name, prog)| {
+                    prog.load_log().map(|log| Error::ProgramLoad {
+                        name: name.clone(),
+                        errno,
+                        log,
+                    })
+                })
+                .unwrap_or(Error::System(errno));
+            return Err(err);
         }
 
         let obj = Object::new(self.ptr)?;
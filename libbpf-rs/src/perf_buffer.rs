@@ -1,8 +1,12 @@
 use core::ffi::c_void;
 use std::boxed::Box;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
 use std::fmt::Debug;
 use std::fmt::Formatter;
 use std::fmt::Result as FmtResult;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::slice;
 use std::time::Duration;
 
@@ -17,27 +21,95 @@ impl<T> SampleCb for T where T: FnMut(i32, &[u8]) {}
 pub trait LostCb: FnMut(i32, u64) {}
 impl<T> LostCb for T where T: FnMut(i32, u64) {}
 
+pub trait EventCb: FnMut(i32, &libbpf_sys::perf_event_header, &[u8]) {}
+impl<T> EventCb for T where T: FnMut(i32, &libbpf_sys::perf_event_header, &[u8]) {}
+
 struct CbStruct<'b> {
     sample_cb: Option<Box<dyn SampleCb + 'b>>,
     lost_cb: Option<Box<dyn LostCb + 'b>>,
+    event_cb: Option<Box<dyn EventCb + 'b>>,
+    /// The number of lost samples, per CPU.
+    lost: BTreeMap<i32, u64>,
+}
+
+impl CbStruct<'_> {
+    /// Process a record of the given `cpu`, with `data` being what follows
+    /// the `header`.
+    fn process(&mut self, cpu: i32, header: &libbpf_sys::perf_event_header, data: &[u8]) {
+        match header.type_ {
+            libbpf_sys::PERF_RECORD_LOST => {
+                // struct { u64 id; u64 lost; }
+                if let Some(lost) = data.get(8..16) {
+                    let lost = u64::from_ne_bytes(lost.try_into().unwrap());
+                    *self.lost.entry(cpu).or_default() += lost;
+
+                    if let (None, Some(cb)) = (&self.event_cb, &mut self.lost_cb) {
+                        cb(cpu, lost);
+                    }
+                }
+            }
+            libbpf_sys::PERF_RECORD_SAMPLE if self.event_cb.is_none() => {
+                // struct { u32 size; char data[size]; }, as we only request
+                // raw samples.
+                if let Some(cb) = &mut self.sample_cb {
+                    let size = data
+                        .get(..4)
+                        .map(|size| u32::from_ne_bytes(size.try_into().unwrap()) as usize);
+                    if let Some(sample) = size.and_then(|size| data.get(4..4 + size)) {
+                        cb(cpu, sample);
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        if let Some(cb) = &mut self.event_cb {
+            cb(cpu, header, data);
+        }
+    }
 }
 
 impl Debug for CbStruct<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        let Self { sample_cb, lost_cb } = self;
+        let Self {
+            sample_cb,
+            lost_cb,
+            event_cb,
+            lost,
+        } = self;
         f.debug_struct("CbStruct")
             .field("sample_cb", &sample_cb.as_ref().map(|cb| &cb as *const _))
             .field("lost_cb", &lost_cb.as_ref().map(|cb| &cb as *const _))
+            .field("event_cb", &event_cb.as_ref().map(|cb| &cb as *const _))
+            .field("lost", lost)
             .finish()
     }
 }
 
+/// The perf event attributes `libbpf` uses for perf buffers by default.
+fn default_attr() -> libbpf_sys::perf_event_attr {
+    let mut attr = libbpf_sys::perf_event_attr {
+        type_: libbpf_sys::PERF_TYPE_SOFTWARE,
+        size: mem::size_of::<libbpf_sys::perf_event_attr>() as u32,
+        config: libbpf_sys::PERF_COUNT_SW_BPF_OUTPUT as u64,
+        sample_type: libbpf_sys::PERF_SAMPLE_RAW as u64,
+        ..Default::default()
+    };
+    attr.__bindgen_anon_1.sample_period = 1;
+    attr.__bindgen_anon_2.wakeup_events = 1;
+    attr
+}
+
 /// Builds [`PerfBuffer`] instances.
 pub struct PerfBufferBuilder<'a, 'b> {
     map: &'a Map,
     pages: usize,
     sample_cb: Option<Box<dyn SampleCb + 'b>>,
     lost_cb: Option<Box<dyn LostCb + 'b>>,
+    event_cb: Option<Box<dyn EventCb + 'b>>,
+    attr: libbpf_sys::perf_event_attr,
+    cpus: Option<Vec<i32>>,
+    map_keys: Option<Vec<i32>>,
 }
 
 impl<'a, 'b> PerfBufferBuilder<'a, 'b> {
@@ -48,6 +120,10 @@ impl<'a, 'b> PerfBufferBuilder<'a, 'b> {
             pages: 64,
             sample_cb: None,
             lost_cb: None,
+            event_cb: None,
+            attr: default_attr(),
+            cpus: None,
+            map_keys: None,
         }
     }
 }
@@ -59,34 +135,91 @@ impl<'a, 'b> PerfBufferBuilder<'a, 'b> {
     /// [`plain`](https://crates.io/crates/plain) helpful.
     ///
     /// Callback arguments are: `(cpu, data)`.
+    ///
+    /// Not invoked if an event callback is set via
+    /// [`PerfBufferBuilder::event_cb()`].
     pub fn sample_cb<NewCb: SampleCb + 'b>(self, cb: NewCb) -> PerfBufferBuilder<'a, 'b> {
         PerfBufferBuilder {
-            map: self.map,
-            pages: self.pages,
             sample_cb: Some(Box::new(cb)),
-            lost_cb: self.lost_cb,
+            ..self
         }
     }
 
     /// Callback to run when a sample is received.
     ///
     /// Callback arguments are: `(cpu, lost_count)`.
+    ///
+    /// Not invoked if an event callback is set via
+    /// [`PerfBufferBuilder::event_cb()`].
     pub fn lost_cb<NewCb: LostCb + 'b>(self, cb: NewCb) -> PerfBufferBuilder<'a, 'b> {
         PerfBufferBuilder {
-            map: self.map,
-            pages: self.pages,
-            sample_cb: self.sample_cb,
             lost_cb: Some(Box::new(cb)),
+            ..self
+        }
+    }
+
+    /// Callback to run for every record read from the buffers, including
+    /// those that are neither samples nor reports of lost samples. Takes
+    /// precedence over the sample and lost callbacks.
+    ///
+    /// Callback arguments are: `(cpu, header, data)`, with `data` being the
+    /// contents of the record following the header.
+    pub fn event_cb<NewCb: EventCb + 'b>(self, cb: NewCb) -> PerfBufferBuilder<'a, 'b> {
+        PerfBufferBuilder {
+            event_cb: Some(Box::new(cb)),
+            ..self
         }
     }
 
     /// The number of pages to size the ring buffer.
     pub fn pages(self, pages: usize) -> PerfBufferBuilder<'a, 'b> {
+        PerfBufferBuilder { pages, ..self }
+    }
+
+    /// The attributes of the perf events to open, replacing those for
+    /// `PERF_COUNT_SW_BPF_OUTPUT` events producing raw samples used by
+    /// default.
+    ///
+    /// Unless an event callback is set via [`PerfBufferBuilder::event_cb()`],
+    /// `PERF_SAMPLE_RAW` must remain the only sample type, as samples can't
+    /// be passed to the sample callback otherwise.
+    pub fn attr(self, attr: libbpf_sys::perf_event_attr) -> PerfBufferBuilder<'a, 'b> {
+        PerfBufferBuilder { attr, ..self }
+    }
+
+    /// Wake up pollers every `count` events, instead of on every event.
+    pub fn wakeup_events(mut self, count: u32) -> PerfBufferBuilder<'a, 'b> {
+        self.attr.set_watermark(0);
+        self.attr.__bindgen_anon_2.wakeup_events = count;
+        self
+    }
+
+    /// Wake up pollers once `bytes` bytes are pending in a buffer, instead of
+    /// on every event.
+    pub fn wakeup_watermark(mut self, bytes: u32) -> PerfBufferBuilder<'a, 'b> {
+        self.attr.set_watermark(1);
+        self.attr.__bindgen_anon_2.wakeup_watermark = bytes;
+        self
+    }
+
+    /// Only open buffers for the given `cpus`, instead of for all online
+    /// ones.
+    ///
+    /// By default, the buffer of each CPU is stored in the map using the
+    /// CPU's id as key. See [`PerfBufferBuilder::map_keys()`].
+    pub fn cpus(self, cpus: &[i32]) -> PerfBufferBuilder<'a, 'b> {
         PerfBufferBuilder {
-            map: self.map,
-            pages,
-            sample_cb: self.sample_cb,
-            lost_cb: self.lost_cb,
+            cpus: Some(cpus.to_vec()),
+            ..self
+        }
+    }
+
+    /// The keys to store the buffers of the CPUs selected via
+    /// [`PerfBufferBuilder::cpus()`] under in the map, one per CPU.
+    pub fn map_keys(self, map_keys: &[i32]) -> PerfBufferBuilder<'a, 'b> {
+        PerfBufferBuilder {
+            map_keys: Some(map_keys.to_vec()),
+            ..self
         }
     }
 
@@ -104,58 +237,80 @@ impl<'a, 'b> PerfBufferBuilder<'a, 'b> {
             ));
         }
 
-        let c_sample_cb: libbpf_sys::perf_buffer_sample_fn = if self.sample_cb.is_some() {
-            Some(Self::call_sample_cb)
-        } else {
-            None
-        };
-
-        let c_lost_cb: libbpf_sys::perf_buffer_lost_fn = if self.lost_cb.is_some() {
-            Some(Self::call_lost_cb)
-        } else {
-            None
+        let mut opts = libbpf_sys::perf_buffer_raw_opts {
+            sz: mem::size_of::<libbpf_sys::perf_buffer_raw_opts>() as libbpf_sys::size_t,
+            ..Default::default()
         };
+        match (&self.cpus, &self.map_keys) {
+            (Some(cpus), map_keys) => {
+                let map_keys = map_keys.as_ref().unwrap_or(cpus);
+                if cpus.is_empty() {
+                    return Err(Error::InvalidInput(
+                        "At least one CPU must be selected".to_string(),
+                    ));
+                }
+                if map_keys.len() != cpus.len() {
+                    return Err(Error::InvalidInput(format!(
+                        "Number of map keys ({}) does not match number of CPUs ({})",
+                        map_keys.len(),
+                        cpus.len()
+                    )));
+                }
+                opts.cpu_cnt = cpus.len() as i32;
+                // libbpf does not modify either array.
+                opts.cpus = cpus.as_ptr() as *mut _;
+                opts.map_keys = map_keys.as_ptr() as *mut _;
+            }
+            (None, Some(_)) => {
+                return Err(Error::InvalidInput(
+                    "Map keys require CPUs to be selected".to_string(),
+                ))
+            }
+            (None, None) => (),
+        }
 
         let callback_struct_ptr = Box::into_raw(Box::new(CbStruct {
             sample_cb: self.sample_cb,
             lost_cb: self.lost_cb,
+            event_cb: self.event_cb,
+            lost: BTreeMap::new(),
         }));
 
+        let mut attr = self.attr;
         let ptr = unsafe {
-            libbpf_sys::perf_buffer__new(
+            libbpf_sys::perf_buffer__new_raw(
                 self.map.fd(),
                 self.pages as libbpf_sys::size_t,
-                c_sample_cb,
-                c_lost_cb,
+                &mut attr,
+                Some(Self::call_event_cb),
                 callback_struct_ptr as *mut _,
-                std::ptr::null(),
+                &opts,
             )
         };
+        let cb_struct = unsafe { Box::from_raw(callback_struct_ptr) };
         let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
         if err != 0 {
             Err(Error::System(err as i32))
         } else {
-            Ok(PerfBuffer {
-                ptr,
-                _cb_struct: unsafe { Box::from_raw(callback_struct_ptr) },
-            })
+            Ok(PerfBuffer { ptr, cb_struct })
         }
     }
 
-    unsafe extern "C" fn call_sample_cb(ctx: *mut c_void, cpu: i32, data: *mut c_void, size: u32) {
+    unsafe extern "C" fn call_event_cb(
+        ctx: *mut c_void,
+        cpu: i32,
+        event: *mut libbpf_sys::perf_event_header,
+    ) -> libbpf_sys::bpf_perf_event_ret {
         let callback_struct = ctx as *mut CbStruct;
-
-        if let Some(cb) = &mut (*callback_struct).sample_cb {
-            cb(cpu, slice::from_raw_parts(data as *const u8, size as usize));
-        }
-    }
-
-    unsafe extern "C" fn call_lost_cb(ctx: *mut c_void, cpu: i32, count: u64) {
-        let callback_struct = ctx as *mut CbStruct;
-
-        if let Some(cb) = &mut (*callback_struct).lost_cb {
-            cb(cpu, count);
-        }
+        let header = &*event;
+        let header_size = mem::size_of::<libbpf_sys::perf_event_header>();
+        let data = slice::from_raw_parts(
+            (event as *const u8).add(header_size),
+            (header.size as usize).saturating_sub(header_size),
+        );
+
+        (*callback_struct).process(cpu, header, data);
+        libbpf_sys::LIBBPF_PERF_EVENT_CONT
     }
 }
 
@@ -166,13 +321,20 @@ impl Debug for PerfBufferBuilder<'_, '_> {
             pages,
             sample_cb,
             lost_cb,
+            event_cb,
+            attr: _,
+            cpus,
+            map_keys,
         } = self;
         f.debug_struct("PerfBufferBuilder")
             .field("map", map)
             .field("pages", pages)
             .field("sample_cb", &sample_cb.as_ref().map(|cb| &cb as *const _))
             .field("lost_cb", &lost_cb.as_ref().map(|cb| &cb as *const _))
-            .finish()
+            .field("event_cb", &event_cb.as_ref().map(|cb| &cb as *const _))
+            .field("cpus", cpus)
+            .field("map_keys", map_keys)
+            .finish_non_exhaustive()
     }
 }
 
@@ -180,9 +342,9 @@ impl Debug for PerfBufferBuilder<'_, '_> {
 /// [`Program`]s and userspace.
 #[derive(Debug)]
 pub struct PerfBuffer<'b> {
-    ptr: *mut libbpf_sys::perf_buffer,
+    pub(crate) ptr: *mut libbpf_sys::perf_buffer,
     // Hold onto the box so it'll get dropped when PerfBuffer is dropped
-    _cb_struct: Box<CbStruct<'b>>,
+    cb_struct: Box<CbStruct<'b>>,
 }
 
 // TODO: Document methods.
@@ -218,6 +380,24 @@ impl<'b> PerfBuffer<'b> {
             unsafe { libbpf_sys::perf_buffer__buffer_fd(self.ptr, buf_idx as libbpf_sys::size_t) };
         util::parse_ret_i32(ret)
     }
+
+    /// The number of samples the kernel reported as lost on `cpu`, because
+    /// its buffer was full.
+    pub fn lost_count(&self, cpu: i32) -> u64 {
+        self.cb_struct.lost.get(&cpu).copied().unwrap_or(0)
+    }
+
+    /// The number of samples the kernel reported as lost, summed up over all
+    /// CPUs.
+    pub fn total_lost_count(&self) -> u64 {
+        self.cb_struct.lost.values().sum()
+    }
+}
+
+impl AsRawFd for PerfBuffer<'_> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.epoll_fd()
+    }
 }
 
 // SAFETY: `perf_buffer` objects can safely be polled from any thread.
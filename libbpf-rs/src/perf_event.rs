@@ -0,0 +1,286 @@
+use std::fs;
+use std::io;
+use std::mem;
+
+use nix::{errno, libc, unistd};
+
+use crate::*;
+
+/// Directories tracefs may be mounted at, in order of preference.
+const TRACEFS_PATHS: [&str; 2] = ["/sys/kernel/tracing", "/sys/kernel/debug/tracing"];
+
+/// A generalized hardware event, as counted by the CPU's PMU.
+#[non_exhaustive]
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum HardwareEvent {
+    CpuCycles = libbpf_sys::PERF_COUNT_HW_CPU_CYCLES,
+    Instructions = libbpf_sys::PERF_COUNT_HW_INSTRUCTIONS,
+    CacheReferences = libbpf_sys::PERF_COUNT_HW_CACHE_REFERENCES,
+    CacheMisses = libbpf_sys::PERF_COUNT_HW_CACHE_MISSES,
+    BranchInstructions = libbpf_sys::PERF_COUNT_HW_BRANCH_INSTRUCTIONS,
+    BranchMisses = libbpf_sys::PERF_COUNT_HW_BRANCH_MISSES,
+    BusCycles = libbpf_sys::PERF_COUNT_HW_BUS_CYCLES,
+    StalledCyclesFrontend = libbpf_sys::PERF_COUNT_HW_STALLED_CYCLES_FRONTEND,
+    StalledCyclesBackend = libbpf_sys::PERF_COUNT_HW_STALLED_CYCLES_BACKEND,
+    RefCpuCycles = libbpf_sys::PERF_COUNT_HW_REF_CPU_CYCLES,
+}
+
+/// A software event, as counted by the kernel.
+#[non_exhaustive]
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum SoftwareEvent {
+    CpuClock = libbpf_sys::PERF_COUNT_SW_CPU_CLOCK,
+    TaskClock = libbpf_sys::PERF_COUNT_SW_TASK_CLOCK,
+    PageFaults = libbpf_sys::PERF_COUNT_SW_PAGE_FAULTS,
+    ContextSwitches = libbpf_sys::PERF_COUNT_SW_CONTEXT_SWITCHES,
+    CpuMigrations = libbpf_sys::PERF_COUNT_SW_CPU_MIGRATIONS,
+    PageFaultsMin = libbpf_sys::PERF_COUNT_SW_PAGE_FAULTS_MIN,
+    PageFaultsMaj = libbpf_sys::PERF_COUNT_SW_PAGE_FAULTS_MAJ,
+    AlignmentFaults = libbpf_sys::PERF_COUNT_SW_ALIGNMENT_FAULTS,
+    EmulationFaults = libbpf_sys::PERF_COUNT_SW_EMULATION_FAULTS,
+    Dummy = libbpf_sys::PERF_COUNT_SW_DUMMY,
+    CgroupSwitches = libbpf_sys::PERF_COUNT_SW_CGROUP_SWITCHES,
+}
+
+/// The kind of memory access a [`PerfEvent::Breakpoint`] triggers on. Maps to
+/// `HW_BREAKPOINT_*` in kernel uapi.
+#[non_exhaustive]
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum BreakpointType {
+    Read = 1,
+    Write = 2,
+    ReadWrite = 3,
+    Execute = 4,
+}
+
+/// An event to open perf events for via [`PerfEventBuilder`].
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PerfEvent {
+    /// A hardware event.
+    Hardware(HardwareEvent),
+    /// A software event.
+    Software(SoftwareEvent),
+    /// A kernel tracepoint, e.g., `sched`/`sched_switch`.
+    Tracepoint {
+        /// The category of the tracepoint.
+        category: String,
+        /// The name of the tracepoint.
+        name: String,
+    },
+    /// A hardware breakpoint.
+    Breakpoint {
+        /// The address to watch.
+        addr: u64,
+        /// The number of bytes to watch, usually 1, 2, 4, or 8.
+        len: u64,
+        /// The kind of access to trigger on.
+        ty: BreakpointType,
+    },
+}
+
+/// How often a perf event invokes the programs attached to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleRate {
+    /// Invoke attached programs every `n` events.
+    Period(u64),
+    /// Invoke attached programs `n` times per second, adjusting the period
+    /// on the fly.
+    Frequency(u64),
+}
+
+/// Opens perf events and attaches [`Program`]s to them, e.g., to sample what
+/// CPUs are doing.
+///
+/// By default, one event is opened per online CPU, counting events of all
+/// processes, and attached programs are invoked for every event.
+#[derive(Clone, Debug)]
+pub struct PerfEventBuilder {
+    event: PerfEvent,
+    sample_rate: SampleRate,
+    pid: Option<i32>,
+    cpus: Option<Vec<i32>>,
+}
+
+impl PerfEventBuilder {
+    /// Create a new `PerfEventBuilder` for the given `event`.
+    pub fn new(event: PerfEvent) -> Self {
+        Self {
+            event,
+            sample_rate: SampleRate::Period(1),
+            pid: None,
+            cpus: None,
+        }
+    }
+
+    /// Set how often attached programs are invoked.
+    pub fn sample_rate(&mut self, sample_rate: SampleRate) -> &mut Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// Only count events of the process or thread with the given `pid`.
+    ///
+    /// Unless CPUs are selected via [`PerfEventBuilder::cpus()`], a single
+    /// event following the process across all CPUs is opened.
+    pub fn pid(&mut self, pid: i32) -> &mut Self {
+        self.pid = Some(pid);
+        self
+    }
+
+    /// Open one event for each of the given `cpus`, instead of for all online
+    /// CPUs.
+    pub fn cpus(&mut self, cpus: &[i32]) -> &mut Self {
+        self.cpus = Some(cpus.to_vec());
+        self
+    }
+
+    /// Open the configured perf events and attach `prog` to each of them,
+    /// returning one [`Link`] per event.
+    ///
+    /// Dropping a link closes its perf event.
+    pub fn attach(&self, prog: &mut Program) -> Result<Vec<Link>> {
+        let attr = self.attr()?;
+        let cpus = match (&self.cpus, self.pid) {
+            (Some(cpus), _) => cpus.clone(),
+            // Follow the process across all CPUs.
+            (None, Some(_)) => vec![-1],
+            (None, None) => online_cpus()?,
+        };
+        // A pid of -1 counts all processes.
+        let pid = self.pid.unwrap_or(-1);
+
+        cpus.into_iter()
+            .map(|cpu| {
+                let pfd = perf_event_open(&attr, pid, cpu)
+                    .with_context(|| format!("open perf event on CPU {}", cpu))?;
+                // The link takes ownership of the perf event, but only if it
+                // got created.
+                prog.attach_perf_event(pfd).map_err(|err| {
+                    let _ = unistd::close(pfd);
+                    err
+                })
+            })
+            .collect()
+    }
+
+    fn attr(&self) -> Result<libbpf_sys::perf_event_attr> {
+        let mut attr = libbpf_sys::perf_event_attr {
+            size: mem::size_of::<libbpf_sys::perf_event_attr>() as u32,
+            ..Default::default()
+        };
+
+        match &self.event {
+            PerfEvent::Hardware(event) => {
+                attr.type_ = libbpf_sys::PERF_TYPE_HARDWARE;
+                attr.config = *event as u64;
+            }
+            PerfEvent::Software(event) => {
+                attr.type_ = libbpf_sys::PERF_TYPE_SOFTWARE;
+                attr.config = *event as u64;
+            }
+            PerfEvent::Tracepoint { category, name } => {
+                attr.type_ = libbpf_sys::PERF_TYPE_TRACEPOINT;
+                attr.config = tracepoint_id(category, name)?;
+            }
+            PerfEvent::Breakpoint { addr, len, ty } => {
+                attr.type_ = libbpf_sys::PERF_TYPE_BREAKPOINT;
+                attr.bp_type = *ty as u32;
+                attr.__bindgen_anon_3.bp_addr = *addr;
+                attr.__bindgen_anon_4.bp_len = *len;
+            }
+        }
+
+        match self.sample_rate {
+            SampleRate::Period(period) => attr.__bindgen_anon_1.sample_period = period,
+            SampleRate::Frequency(freq) => {
+                attr.set_freq(1);
+                attr.__bindgen_anon_1.sample_freq = freq;
+            }
+        }
+        Ok(attr)
+    }
+}
+
+/// Open a perf event as described by `attr`, returning its fd.
+fn perf_event_open(attr: &libbpf_sys::perf_event_attr, pid: i32, cpu: i32) -> Result<i32> {
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_perf_event_open,
+            attr as *const _,
+            pid,
+            cpu,
+            -1,
+            libbpf_sys::PERF_FLAG_FD_CLOEXEC,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::System(errno::errno()));
+    }
+    Ok(ret as i32)
+}
+
+/// Look up the id of a tracepoint in tracefs.
+fn tracepoint_id(category: &str, name: &str) -> Result<u64> {
+    let mut last_err = io::Error::from(io::ErrorKind::NotFound);
+    for tracefs in TRACEFS_PATHS {
+        let path = format!("{}/events/{}/{}/id", tracefs, category, name);
+        match fs::read_to_string(&path) {
+            Ok(id) => {
+                return id.trim().parse().map_err(|_| {
+                    Error::Internal(format!("invalid tracepoint id in {}: {}", path, id))
+                })
+            }
+            Err(err) => last_err = err,
+        }
+    }
+
+    Err(Error::from(last_err).context(format!("look up tracepoint `{}:{}`", category, name)))
+}
+
+/// Retrieve the ids of all online CPUs.
+fn online_cpus() -> Result<Vec<i32>> {
+    let path = "/sys/devices/system/cpu/online";
+    let content = fs::read_to_string(path)
+        .map_err(|err| Error::from(err).context(format!("read {}", path)))?;
+    parse_cpu_list(&content)
+}
+
+/// Parse a list of CPUs such as "0-3,5", as found in sysfs.
+fn parse_cpu_list(list: &str) -> Result<Vec<i32>> {
+    let invalid = || Error::Internal(format!("invalid CPU list: {}", list));
+    let mut cpus = vec![];
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (start, end),
+            None => (range, range),
+        };
+        let start = start.parse::<i32>().map_err(|_| invalid())?;
+        let end = end.parse::<i32>().map_err(|_| invalid())?;
+        if start > end {
+            return Err(invalid());
+        }
+        cpus.extend(start..=end);
+    }
+    Ok(cpus)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0\n").unwrap(), vec![0]);
+        assert_eq!(parse_cpu_list("0-3,5\n").unwrap(), vec![0, 1, 2, 3, 5]);
+        assert_eq!(parse_cpu_list("1,4-5").unwrap(), vec![1, 4, 5]);
+        assert!(parse_cpu_list("3-1").is_err());
+        assert!(parse_cpu_list("a-b").is_err());
+    }
+}
@@ -1,15 +1,86 @@
+use core::ffi::c_void;
 use std::convert::TryFrom;
 use std::ffi::CStr;
+use std::fs::File;
 use std::mem;
+use std::os::raw::c_char;
+use std::os::unix::io::AsRawFd;
 use std::path::Path;
 use std::ptr;
+use std::slice;
+use std::time::Duration;
 
 use libbpf_sys::bpf_func_id;
+use nix::{errno, libc, unistd};
 use num_enum::TryFromPrimitive;
 use strum_macros::Display;
 
 use crate::*;
 
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"),
+    not(target_env = "musl")
+))]
+use nix::libc::SO_DETACH_REUSEPORT_BPF;
+
+/// `SO_DETACH_REUSEPORT_BPF`, for targets `libc` does not provide it for.
+/// The values are taken from the kernel's `asm/socket.h` of the respective
+/// architecture.
+#[cfg(all(
+    not(all(
+        target_os = "linux",
+        any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"),
+        not(target_env = "musl")
+    )),
+    any(
+        target_arch = "x86",
+        target_arch = "x86_64",
+        target_arch = "aarch64",
+        target_arch = "arm",
+        target_arch = "csky",
+        target_arch = "hexagon",
+        target_arch = "loongarch64",
+        target_arch = "m68k",
+        target_arch = "mips",
+        target_arch = "mips64",
+        target_arch = "powerpc",
+        target_arch = "powerpc64",
+        target_arch = "riscv32",
+        target_arch = "riscv64",
+        target_arch = "s390x"
+    )
+))]
+const SO_DETACH_REUSEPORT_BPF: i32 = 68;
+#[cfg(any(target_arch = "sparc", target_arch = "sparc64"))]
+const SO_DETACH_REUSEPORT_BPF: i32 = 0x0047;
+// Architectures not listed above may use a value other than the generic one
+// (e.g., parisc uses 0x4042), so refuse to guess.
+#[cfg(not(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "arm",
+    target_arch = "csky",
+    target_arch = "hexagon",
+    target_arch = "loongarch64",
+    target_arch = "m68k",
+    target_arch = "mips",
+    target_arch = "mips64",
+    target_arch = "powerpc",
+    target_arch = "powerpc64",
+    target_arch = "riscv32",
+    target_arch = "riscv64",
+    target_arch = "s390x",
+    target_arch = "sparc",
+    target_arch = "sparc64"
+)))]
+compile_error!("the value of SO_DETACH_REUSEPORT_BPF is not known for this architecture");
+
+/// The size of the verifier log buffer used if a log level but no size was
+/// configured. Matches the size of the buffer libbpf allocates itself.
+const DEFAULT_LOG_SIZE: usize = (u32::MAX >> 8) as usize;
+
 /// Options to optionally be provided when attaching to a uprobe.
 #[derive(Clone, Debug, Default)]
 pub struct UprobeOpts {
@@ -77,20 +148,265 @@ impl From<TracepointOpts> for libbpf_sys::bpf_tracepoint_opts {
     }
 }
 
+/// How a kernel or user space probe gets attached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProbeAttachMode {
+    /// Let libbpf pick the most capable mode the kernel supports.
+    Default,
+    /// Create the probe via tracefs and attach to it via the perf event
+    /// ioctl.
+    Legacy,
+    /// Create the probe via the perf event PMU and attach to it via the
+    /// perf event ioctl.
+    Perf,
+    /// Create the probe via the perf event PMU and attach to it via a BPF
+    /// link.
+    Link,
+}
+
+impl Default for ProbeAttachMode {
+    fn default() -> Self {
+        Self::Default
+    }
+}
+
+impl From<ProbeAttachMode> for libbpf_sys::probe_attach_mode {
+    fn from(mode: ProbeAttachMode) -> Self {
+        match mode {
+            ProbeAttachMode::Default => libbpf_sys::PROBE_ATTACH_MODE_DEFAULT,
+            ProbeAttachMode::Legacy => libbpf_sys::PROBE_ATTACH_MODE_LEGACY,
+            ProbeAttachMode::Perf => libbpf_sys::PROBE_ATTACH_MODE_PERF,
+            ProbeAttachMode::Link => libbpf_sys::PROBE_ATTACH_MODE_LINK,
+        }
+    }
+}
+
+/// Options to optionally be provided when attaching to a kprobe.
+#[derive(Clone, Debug, Default)]
+pub struct KprobeOpts {
+    /// Custom user-provided value accessible through `bpf_get_attach_cookie`.
+    pub cookie: u64,
+    /// Offset of the probe within the function to attach to.
+    pub offset: usize,
+    /// kprobe is return probe, invoked at function return time.
+    pub retprobe: bool,
+    /// How to attach the probe.
+    pub attach_mode: ProbeAttachMode,
+    /// The maximum number of concurrently active instances of a return
+    /// probe, or 0 for the kernel's default.
+    ///
+    /// libbpf does not support setting it, so attaching fails with
+    /// [`ErrorKind::Unsupported`] for values other than 0.
+    pub maxactive: u32,
+    #[doc(hidden)]
+    pub _non_exhaustive: (),
+}
+
+impl From<KprobeOpts> for libbpf_sys::bpf_kprobe_opts {
+    fn from(opts: KprobeOpts) -> Self {
+        let KprobeOpts {
+            cookie,
+            offset,
+            retprobe,
+            attach_mode,
+            // Checked when attaching, as there is no counterpart for it.
+            maxactive: _,
+            _non_exhaustive,
+        } = opts;
+
+        libbpf_sys::bpf_kprobe_opts {
+            sz: mem::size_of::<Self>() as u64,
+            bpf_cookie: cookie,
+            offset: offset as libbpf_sys::size_t,
+            retprobe,
+            attach_mode: attach_mode.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Options to be provided when attaching to multiple kernel probes at once.
+///
+/// The kernel functions to attach to are selected via exactly one of
+/// `pattern`, `symbols`, or `addresses`.
+#[derive(Clone, Debug, Default)]
+pub struct KprobeMultiOpts {
+    /// Glob pattern, e.g., `tcp_*`, matched against the names of the kernel
+    /// functions available for tracing.
+    pub pattern: Option<String>,
+    /// Names of the kernel functions to attach to.
+    pub symbols: Vec<String>,
+    /// Addresses of the kernel functions to attach to.
+    pub addresses: Vec<usize>,
+    /// Custom user-provided values accessible through
+    /// `bpf_get_attach_cookie`, one per symbol or address. May be left empty.
+    pub cookies: Vec<u64>,
+    /// Attach to function returns instead of function entries.
+    pub retprobe: bool,
+    #[doc(hidden)]
+    pub _non_exhaustive: (),
+}
+
+/// Options to be provided when attaching to multiple user space probes in a
+/// single binary at once.
+///
+/// libbpf v1.1 does not offer resolving function names to offsets for
+/// uprobe multi links, so locations are provided as offsets only.
+#[derive(Clone, Debug, Default)]
+pub struct UprobeMultiOpts {
+    /// File offsets of the instructions to attach to, e.g., of the first
+    /// instruction of functions.
+    pub offsets: Vec<usize>,
+    /// File offsets of USDT semaphores to increment while attached, one per
+    /// offset. May be left empty.
+    pub ref_ctr_offsets: Vec<usize>,
+    /// Custom user-provided values accessible through
+    /// `bpf_get_attach_cookie`, one per offset. May be left empty.
+    pub cookies: Vec<u64>,
+    /// Attach to function returns instead of function entries.
+    pub retprobe: bool,
+    #[doc(hidden)]
+    pub _non_exhaustive: (),
+}
+
+/// Options to optionally be provided when attaching to a fentry/fexit
+/// kernel probe.
+#[derive(Clone, Debug, Default)]
+pub struct TraceOpts {
+    /// Custom user-provided value accessible through `bpf_get_attach_cookie`.
+    pub cookie: u64,
+    #[doc(hidden)]
+    pub _non_exhaustive: (),
+}
+
+impl From<TraceOpts> for libbpf_sys::bpf_trace_opts {
+    fn from(opts: TraceOpts) -> Self {
+        let TraceOpts {
+            cookie,
+            _non_exhaustive,
+        } = opts;
+
+        libbpf_sys::bpf_trace_opts {
+            sz: mem::size_of::<Self>() as u64,
+            cookie,
+        }
+    }
+}
+
+/// Options to be provided when attaching to a cgroup via
+/// [`Program::attach_cgroup_legacy()`].
+#[derive(Clone, Debug, Default)]
+pub struct CgroupAttachOpts {
+    /// Allow programs attached to descendant cgroups to override this one
+    /// (`BPF_F_ALLOW_OVERRIDE`).
+    pub allow_override: bool,
+    /// Allow for multiple programs to be attached to the cgroup, all of
+    /// which run (`BPF_F_ALLOW_MULTI`).
+    pub allow_multi: bool,
+    /// The fd of a program attached to the cgroup to atomically replace
+    /// (`BPF_F_REPLACE`). Requires `allow_multi`.
+    pub replace_prog_fd: Option<i32>,
+    #[doc(hidden)]
+    pub _non_exhaustive: (),
+}
+
+impl From<CgroupAttachOpts> for libbpf_sys::bpf_prog_attach_opts {
+    fn from(opts: CgroupAttachOpts) -> Self {
+        let CgroupAttachOpts {
+            allow_override,
+            allow_multi,
+            replace_prog_fd,
+            _non_exhaustive,
+        } = opts;
+
+        let mut flags = 0;
+        if allow_override {
+            flags |= libbpf_sys::BPF_F_ALLOW_OVERRIDE;
+        }
+        if allow_multi {
+            flags |= libbpf_sys::BPF_F_ALLOW_MULTI;
+        }
+        if replace_prog_fd.is_some() {
+            flags |= libbpf_sys::BPF_F_REPLACE;
+        }
+
+        libbpf_sys::bpf_prog_attach_opts {
+            sz: mem::size_of::<Self>() as u64,
+            flags,
+            __bindgen_anon_1: libbpf_sys::bpf_prog_attach_opts__bindgen_ty_1 {
+                replace_prog_fd: replace_prog_fd.unwrap_or(0),
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// Input to [`Program::test_run()`].
+#[derive(Clone, Debug, Default)]
+pub struct TestRunOpts<'dat> {
+    /// The data to run the program on, e.g., a packet.
+    pub data_in: &'dat [u8],
+    /// The size of the buffer receiving the data as modified by the program.
+    /// No data is retrieved if zero.
+    pub data_out_size: usize,
+    /// The context to run the program with, e.g., a `struct __sk_buff`. The
+    /// kernel provides a default context if empty.
+    pub context_in: &'dat [u8],
+    /// The size of the buffer receiving the context as modified by the
+    /// program. No context is retrieved if zero.
+    pub context_out_size: usize,
+    /// The number of times to run the program, to measure its duration. Must
+    /// not exceed `i32::MAX`.
+    pub repeat: u32,
+    /// `BPF_F_TEST_*` flags.
+    pub flags: u32,
+    /// The CPU to run the program on, if `BPF_F_TEST_RUN_ON_CPU` is set.
+    pub cpu: u32,
+    /// The number of frames processed in one batch, with
+    /// `BPF_F_TEST_XDP_LIVE_FRAMES`.
+    pub batch_size: u32,
+    #[doc(hidden)]
+    pub _non_exhaustive: (),
+}
+
+/// Output of [`Program::test_run()`].
+#[derive(Clone, Debug)]
+pub struct TestRunOutput {
+    /// The value the program returned.
+    pub return_value: u32,
+    /// The average duration of a single run.
+    pub duration: Duration,
+    /// The data as modified by the program. The run fails with `ENOSPC` if
+    /// it does not fit into [`TestRunOpts::data_out_size`] bytes.
+    pub data_out: Vec<u8>,
+    /// The context as modified by the program. The run fails with `ENOSPC`
+    /// if it does not fit into [`TestRunOpts::context_out_size`] bytes.
+    pub context_out: Vec<u8>,
+}
+
 /// Represents a parsed but not yet loaded BPF program.
 ///
 /// This object exposes operations that need to happen before the program is loaded.
 #[derive(Debug)]
 pub struct OpenProgram {
-    ptr: *mut libbpf_sys::bpf_program,
+    pub(crate) ptr: *mut libbpf_sys::bpf_program,
     section: String,
+    log_size: Option<usize>,
+    // The buffer the kernel writes the verifier log to. Only allocated right
+    // before the program is loaded, if logging is enabled and a size was set.
+    log_buf: Vec<u8>,
 }
 
 // TODO: Document variants.
 #[allow(missing_docs)]
 impl OpenProgram {
     pub(crate) fn new(ptr: *mut libbpf_sys::bpf_program, section: String) -> Self {
-        Self { ptr, section }
+        Self {
+            ptr,
+            section,
+            log_size: None,
+            log_buf: Vec::new(),
+        }
     }
 
     pub fn set_prog_type(&mut self, prog_type: ProgramType) {
@@ -166,6 +482,76 @@ impl OpenProgram {
         util::parse_ret(ret)
     }
 
+    /// Set the verifier log level used when loading this program.
+    ///
+    /// With the default level of 0, the kernel is only asked for a log once
+    /// loading failed, in which case the program is loaded again with level 1.
+    /// Level 1 logs the verification of the program and level 2 additionally
+    /// logs the verifier's state at every instruction; the values can be
+    /// combined with 4 to include statistics.
+    pub fn set_log_level(&mut self, log_level: u32) -> Result<()> {
+        let ret = unsafe { libbpf_sys::bpf_program__set_log_level(self.ptr, log_level) };
+        util::parse_ret(ret)
+    }
+
+    /// The verifier log level used when loading this program.
+    pub fn log_level(&self) -> u32 {
+        unsafe { libbpf_sys::bpf_program__log_level(self.ptr) }
+    }
+
+    /// Set the size of the buffer capturing the verifier log when loading
+    /// this program.
+    ///
+    /// Setting a size requests the verifier log, which gets reported through
+    /// [`Error::ProgramLoad`] if the program fails to load. With a log level
+    /// of 0 (see [`OpenProgram::set_log_level()`]), the buffer is only filled
+    /// once loading failed. Logs exceeding the buffer are truncated, and
+    /// older kernels fail to load programs whose log does not fit into it.
+    ///
+    /// The buffer is only allocated when the object is loaded. If a non-zero
+    /// log level is set without a size, a buffer of 16 MiB is used.
+    pub fn set_log_size(&mut self, log_size: usize) {
+        self.log_size = Some(log_size);
+    }
+
+    /// Allocate the verifier log buffer, if the log was requested, and hand
+    /// it to libbpf.
+    pub(crate) fn prepare_log_buf(&mut self) -> Result<()> {
+        if !unsafe { libbpf_sys::bpf_program__autoload(self.ptr) } {
+            return Ok(());
+        }
+        let log_size = match self.log_size {
+            Some(log_size) => log_size,
+            None if self.log_level() != 0 => DEFAULT_LOG_SIZE,
+            None => return Ok(()),
+        };
+
+        self.log_buf = vec![0; log_size];
+        // NB: libbpf only uses the buffer while loading the object, so it
+        //     is fine for it to be freed along with `self` afterwards.
+        let ret = unsafe {
+            libbpf_sys::bpf_program__set_log_buf(
+                self.ptr,
+                self.log_buf.as_mut_ptr() as *mut c_char,
+                self.log_buf.len() as libbpf_sys::size_t,
+            )
+        };
+        util::parse_ret(ret)
+    }
+
+    /// The verifier log written while loading this program, if any.
+    pub(crate) fn load_log(&self) -> Option<String> {
+        let len = self
+            .log_buf
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(self.log_buf.len());
+        if len == 0 {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&self.log_buf[..len]).into_owned())
+    }
+
     /// Returns the number of instructions that form the program.
     ///
     /// Note: Keep in mind, libbpf can modify the program's instructions
@@ -315,6 +701,9 @@ pub enum ProgramAttachType {
     SkReuseportSelect,
     SkReuseportSelectOrMigrate,
     PerfEvent,
+    TraceKprobeMulti,
+    /// Not known to libbpf v1.1 and hence not defined by it.
+    TraceUprobeMulti = 48,
     /// See [`MapType::Unknown`]
     Unknown = u32::MAX,
 }
@@ -328,14 +717,89 @@ pub enum ProgramAttachType {
 /// method will fail with the appropriate error.
 #[derive(Debug)]
 pub struct Program {
+    // The ptr will be null if the program was opened by pin path or id, in
+    // which case `fd` is owned by us.
     pub(crate) ptr: *mut libbpf_sys::bpf_program,
+    fd: i32,
+    ty: libbpf_sys::bpf_prog_type,
     name: String,
     section: String,
 }
 
 impl Program {
     pub(crate) fn new(ptr: *mut libbpf_sys::bpf_program, name: String, section: String) -> Self {
-        Program { ptr, name, section }
+        let fd = unsafe { libbpf_sys::bpf_program__fd(ptr) };
+        let ty = unsafe { libbpf_sys::bpf_program__type(ptr) };
+        Program {
+            ptr,
+            fd,
+            ty,
+            name,
+            section,
+        }
+    }
+
+    /// Internal function to create a `Program` taking ownership of the
+    /// program referred to by `fd`, retrieving its attributes from the kernel.
+    ///
+    /// `fd` is closed if this function fails.
+    fn from_owned_fd(fd: i32) -> Result<Program> {
+        let info = match prog_info_by_fd(fd) {
+            Ok(info) => info,
+            Err(err) => {
+                let _ = unistd::close(fd);
+                return Err(err);
+            }
+        };
+
+        Ok(Program {
+            ptr: ptr::null_mut(),
+            fd,
+            ty: info.type_,
+            name: query::name_arr_to_string(&info.name, ""),
+            section: String::new(),
+        })
+    }
+
+    /// Open the program [pinned](https://facebookmicrosites.github.io/bpf/blog/2018/08/31/object-lifetime.html#bpffs)
+    /// at `path`.
+    ///
+    /// The program's name and type are retrieved from the kernel. The
+    /// returned `Program` owns its file descriptor, which is closed once it
+    /// is dropped.
+    ///
+    /// As such a program does not belong to an [`Object`], it has no
+    /// section, its attach type is unknown, and it can only be attached
+    /// using methods that work on the file descriptor, such as
+    /// [`Program::attach_sockmap()`]. The `attach_*` methods creating a
+    /// [`Link`] fail for it.
+    pub fn from_pinned_path<P: AsRef<Path>>(path: P) -> Result<Program> {
+        let fd = util::bpf_obj_get(path.as_ref(), util::BpfObjKind::Program)?;
+        Self::from_owned_fd(fd)
+    }
+
+    /// Open the program with the kernel assigned ID `id`.
+    ///
+    /// The same restrictions as for [`Program::from_pinned_path()`] apply.
+    pub fn from_id(id: u32) -> Result<Program> {
+        let fd = unsafe { libbpf_sys::bpf_prog_get_fd_by_id(id) };
+        if fd < 0 {
+            return Err(Error::System(-fd).context(format!("open program with id {id}")));
+        }
+
+        Self::from_owned_fd(fd)
+    }
+
+    /// Retrieve the libbpf program backing this `Program`, which is only
+    /// available for programs belonging to an [`Object`].
+    fn libbpf_ptr(&self) -> Result<*mut libbpf_sys::bpf_program> {
+        if self.ptr.is_null() {
+            return Err(Error::InvalidInput(format!(
+                "program {} does not belong to an object",
+                self.name,
+            )));
+        }
+        Ok(self.ptr)
     }
 
     /// Retrieve the program's name.
@@ -350,7 +814,7 @@ impl Program {
 
     /// Retrieve the type of the program.
     pub fn prog_type(&self) -> ProgramType {
-        match ProgramType::try_from(unsafe { libbpf_sys::bpf_program__type(self.ptr) }) {
+        match ProgramType::try_from(self.ty) {
             Ok(ty) => ty,
             Err(_) => ProgramType::Unknown,
         }
@@ -358,16 +822,27 @@ impl Program {
 
     /// Returns a file descriptor to the underlying program.
     pub fn fd(&self) -> i32 {
-        unsafe { libbpf_sys::bpf_program__fd(self.ptr) }
+        self.fd
     }
 
     /// Returns flags that have been set for the program.
+    ///
+    /// Always 0 for programs not belonging to an [`Object`].
     pub fn flags(&self) -> u32 {
+        if self.ptr.is_null() {
+            return 0;
+        }
         unsafe { libbpf_sys::bpf_program__flags(self.ptr) }
     }
 
     /// Retrieve the attach type of the program.
+    ///
+    /// Always [`ProgramAttachType::Unknown`] for programs not belonging to an
+    /// [`Object`].
     pub fn attach_type(&self) -> ProgramAttachType {
+        if self.ptr.is_null() {
+            return ProgramAttachType::Unknown;
+        }
         match ProgramAttachType::try_from(unsafe {
             libbpf_sys::bpf_program__expected_attach_type(self.ptr)
         }) {
@@ -382,52 +857,100 @@ impl Program {
         let path_c = util::path_to_cstring(path)?;
         let path_ptr = path_c.as_ptr();
 
-        let ret = unsafe { libbpf_sys::bpf_program__pin(self.ptr, path_ptr) };
-        util::parse_ret(ret)
+        let ret = if self.ptr.is_null() {
+            unsafe { libbpf_sys::bpf_obj_pin(self.fd, path_ptr) }
+        } else {
+            unsafe { libbpf_sys::bpf_program__pin(self.ptr, path_ptr) }
+        };
+        util::parse_ret(ret).with_context(|| format!("pin program `{}`", self.name))
     }
 
     /// [Unpin](https://facebookmicrosites.github.io/bpf/blog/2018/08/31/object-lifetime.html#bpffs)
     /// this program from bpffs
     pub fn unpin<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        let path_c = util::path_to_cstring(path)?;
-        let path_ptr = path_c.as_ptr();
-
-        let ret = unsafe { libbpf_sys::bpf_program__unpin(self.ptr, path_ptr) };
-        util::parse_ret(ret)
+        if self.ptr.is_null() {
+            match std::fs::remove_file(path) {
+                Ok(_) => Ok(()),
+                Err(e) => Err(Error::Internal(format!("remove pin program failed: {e}"))),
+            }
+        } else {
+            let path_c = util::path_to_cstring(path)?;
+            let path_ptr = path_c.as_ptr();
+            let ret = unsafe { libbpf_sys::bpf_program__unpin(self.ptr, path_ptr) };
+            util::parse_ret(ret).with_context(|| format!("unpin program `{}`", self.name))
+        }
     }
 
     /// Auto-attach based on prog section
     pub fn attach(&mut self) -> Result<Link> {
-        let ptr = unsafe { libbpf_sys::bpf_program__attach(self.ptr) };
-        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
-        if err != 0 {
-            Err(Error::System(err as i32))
-        } else {
-            Ok(Link::new(ptr))
-        }
+        let ptr = unsafe { libbpf_sys::bpf_program__attach(self.libbpf_ptr()?) };
+        link_from_ptr(ptr).with_context(|| format!("attach program `{}`", self.name))
     }
 
     /// Attach this program to a
     /// [cgroup](https://www.kernel.org/doc/html/latest/admin-guide/cgroup-v2.html).
     pub fn attach_cgroup(&mut self, cgroup_fd: i32) -> Result<Link> {
-        let ptr = unsafe { libbpf_sys::bpf_program__attach_cgroup(self.ptr, cgroup_fd) };
-        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
-        if err != 0 {
-            Err(Error::System(err as i32))
-        } else {
-            Ok(Link::new(ptr))
+        let ptr = unsafe { libbpf_sys::bpf_program__attach_cgroup(self.libbpf_ptr()?, cgroup_fd) };
+        link_from_ptr(ptr).with_context(|| format!("attach program `{}` to cgroup", self.name))
+    }
+
+    /// Attach this program to the cgroup at `path`, e.g.,
+    /// `/sys/fs/cgroup/foo`. See [`Program::attach_cgroup()`].
+    pub fn attach_cgroup_path<P: AsRef<Path>>(&mut self, path: P) -> Result<Link> {
+        let path = path.as_ref();
+        // The link keeps the cgroup alive, so we don't have to keep it open.
+        let cgroup = File::open(path)
+            .map_err(|err| Error::from(err).context(format!("open cgroup {}", path.display())))?;
+        self.attach_cgroup(cgroup.as_raw_fd())
+    }
+
+    /// Attach this program to a cgroup using the legacy `BPF_PROG_ATTACH`
+    /// command, which predates links and supports older kernels.
+    ///
+    /// `attach_type` selects the cgroup hook, e.g.,
+    /// [`ProgramAttachType::CgroupInetEgress`], and is usually the attach type
+    /// the program was loaded with. It has to be provided explicitly so that
+    /// programs opened via [`Program::from_pinned_path()`] or
+    /// [`Program::from_id()`], whose attach type is not known, can be
+    /// attached as well.
+    ///
+    /// Unlike with links, the attachment is not tied to the lifetime of this
+    /// process. It lasts until detached via
+    /// [`Program::detach_cgroup_legacy()`] or until the cgroup is removed.
+    pub fn attach_cgroup_legacy(
+        &self,
+        cgroup_fd: i32,
+        attach_type: ProgramAttachType,
+        opts: CgroupAttachOpts,
+    ) -> Result<()> {
+        let attach_type = legacy_attach_type(attach_type)?;
+        if opts.replace_prog_fd.is_some() && !opts.allow_multi {
+            return Err(Error::InvalidInput(
+                "replacing a program requires allow_multi".to_string(),
+            ));
         }
+        let opts = libbpf_sys::bpf_prog_attach_opts::from(opts);
+        let ret =
+            unsafe { libbpf_sys::bpf_prog_attach_opts(self.fd, cgroup_fd, attach_type, &opts) };
+        util::parse_ret(ret).with_context(|| format!("attach program `{}` to cgroup", self.name))
+    }
+
+    /// Detach this program from a cgroup it was attached to via
+    /// [`Program::attach_cgroup_legacy()`], using the same `attach_type`.
+    pub fn detach_cgroup_legacy(
+        &self,
+        cgroup_fd: i32,
+        attach_type: ProgramAttachType,
+    ) -> Result<()> {
+        let attach_type = legacy_attach_type(attach_type)?;
+        let ret = unsafe { libbpf_sys::bpf_prog_detach2(self.fd, cgroup_fd, attach_type) };
+        util::parse_ret(ret).with_context(|| format!("detach program `{}` from cgroup", self.name))
     }
 
     /// Attach this program to a [perf event](https://linux.die.net/man/2/perf_event_open).
     pub fn attach_perf_event(&mut self, pfd: i32) -> Result<Link> {
-        let ptr = unsafe { libbpf_sys::bpf_program__attach_perf_event(self.ptr, pfd) };
-        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
-        if err != 0 {
-            Err(Error::System(err as i32))
-        } else {
-            Ok(Link::new(ptr))
-        }
+        let ptr = unsafe { libbpf_sys::bpf_program__attach_perf_event(self.libbpf_ptr()?, pfd) };
+        link_from_ptr(ptr).with_context(|| format!("attach program `{}` to perf event", self.name))
     }
 
     /// Attach this program to a [userspace
@@ -443,19 +966,20 @@ impl Program {
         let path_ptr = path.as_ptr();
         let ptr = unsafe {
             libbpf_sys::bpf_program__attach_uprobe(
-                self.ptr,
+                self.libbpf_ptr()?,
                 retprobe,
                 pid,
                 path_ptr,
                 func_offset as libbpf_sys::size_t,
             )
         };
-        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
-        if err != 0 {
-            Err(Error::System(err as i32))
-        } else {
-            Ok(Link::new(ptr))
-        }
+        link_from_ptr(ptr).with_context(|| {
+            format!(
+                "attach program `{}` to uprobe in `{}`",
+                self.name,
+                path.to_string_lossy()
+            )
+        })
     }
 
     /// Attach this program to a [userspace
@@ -490,19 +1014,20 @@ impl Program {
 
         let ptr = unsafe {
             libbpf_sys::bpf_program__attach_uprobe_opts(
-                self.ptr,
+                self.libbpf_ptr()?,
                 pid,
                 path_ptr,
                 func_offset as libbpf_sys::size_t,
                 &opts as *const _,
             )
         };
-        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
-        if err != 0 {
-            Err(Error::System(err as i32))
-        } else {
-            Ok(Link::new(ptr))
-        }
+        link_from_ptr(ptr).with_context(|| {
+            format!(
+                "attach program `{}` to uprobe in `{}`",
+                self.name,
+                path.to_string_lossy()
+            )
+        })
     }
 
     /// Attach this program to a [kernel
@@ -510,14 +1035,319 @@ impl Program {
     pub fn attach_kprobe<T: AsRef<str>>(&mut self, retprobe: bool, func_name: T) -> Result<Link> {
         let func_name = util::str_to_cstring(func_name.as_ref())?;
         let func_name_ptr = func_name.as_ptr();
-        let ptr =
-            unsafe { libbpf_sys::bpf_program__attach_kprobe(self.ptr, retprobe, func_name_ptr) };
-        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
-        if err != 0 {
-            Err(Error::System(err as i32))
-        } else {
-            Ok(Link::new(ptr))
+        let ptr = unsafe {
+            libbpf_sys::bpf_program__attach_kprobe(self.libbpf_ptr()?, retprobe, func_name_ptr)
+        };
+        link_from_ptr(ptr).with_context(|| {
+            format!(
+                "attach program `{}` to kprobe `{}`",
+                self.name,
+                func_name.to_string_lossy()
+            )
+        })
+    }
+
+    /// Attach this program to a [kernel
+    /// probe](https://www.kernel.org/doc/html/latest/trace/kprobetrace.html),
+    /// providing additional options.
+    pub fn attach_kprobe_with_opts<T: AsRef<str>>(
+        &mut self,
+        func_name: T,
+        opts: KprobeOpts,
+    ) -> Result<Link> {
+        let func_name = util::str_to_cstring(func_name.as_ref())?;
+        if opts.maxactive != 0 {
+            return Err(Error::System(libc::EOPNOTSUPP)
+                .context("libbpf does not support setting maxactive of kretprobes"));
         }
+        let opts = libbpf_sys::bpf_kprobe_opts::from(opts);
+        let ptr = unsafe {
+            libbpf_sys::bpf_program__attach_kprobe_opts(
+                self.libbpf_ptr()?,
+                func_name.as_ptr(),
+                &opts as *const _,
+            )
+        };
+        link_from_ptr(ptr).with_context(|| {
+            format!(
+                "attach program `{}` to kprobe `{}`",
+                self.name,
+                func_name.to_string_lossy()
+            )
+        })
+    }
+
+    /// Attach this program to all kernel functions matching the glob
+    /// `pattern` at once, using a single [`Link`]. See
+    /// [`Program::attach_kprobe_multi_with_opts()`].
+    pub fn attach_kprobe_multi<T: AsRef<str>>(
+        &mut self,
+        retprobe: bool,
+        pattern: T,
+    ) -> Result<Link> {
+        self.attach_kprobe_multi_with_opts(KprobeMultiOpts {
+            pattern: Some(pattern.as_ref().to_string()),
+            retprobe,
+            ..Default::default()
+        })
+    }
+
+    /// Attach this program to multiple kernel functions at once, using a
+    /// single [`Link`].
+    ///
+    /// The program must have been loaded with
+    /// [`ProgramAttachType::TraceKprobeMulti`], as is the case for programs in
+    /// `kprobe.multi` sections. Kprobe multi links are available as of Linux
+    /// 5.18, and attaching fails with [`ErrorKind::Unsupported`] on kernels
+    /// lacking them, so that callers can fall back to
+    /// [`Program::attach_kprobe()`].
+    pub fn attach_kprobe_multi_with_opts(&mut self, opts: KprobeMultiOpts) -> Result<Link> {
+        let KprobeMultiOpts {
+            pattern,
+            symbols,
+            addresses,
+            cookies,
+            retprobe,
+            _non_exhaustive,
+        } = opts;
+
+        let cnt = match (&pattern, symbols.len(), addresses.len()) {
+            (Some(_), 0, 0) => 0,
+            (None, cnt, 0) | (None, 0, cnt) if cnt > 0 => cnt,
+            _ => {
+                return Err(Error::InvalidInput(
+                    "exactly one of pattern, symbols, and addresses must be provided".to_string(),
+                ))
+            }
+        };
+        if !cookies.is_empty() && cookies.len() != cnt {
+            return Err(Error::InvalidInput(format!(
+                "number of cookies ({}) does not match number of symbols or addresses ({})",
+                cookies.len(),
+                cnt
+            )));
+        }
+        if !matches!(self.attach_type(), ProgramAttachType::TraceKprobeMulti) {
+            return Err(Error::InvalidInput(format!(
+                "program `{}` was not loaded with attach type {}",
+                self.name,
+                ProgramAttachType::TraceKprobeMulti
+            )));
+        }
+
+        let pattern = pattern.as_deref().map(util::str_to_cstring).transpose()?;
+        let symbols = symbols
+            .iter()
+            .map(|sym| util::str_to_cstring(sym))
+            .collect::<Result<Vec<_>>>()?;
+        let mut sym_ptrs = symbols.iter().map(|sym| sym.as_ptr()).collect::<Vec<_>>();
+
+        // libbpf tells the different ways of selecting functions apart by
+        // which pointers are set.
+        let opts = libbpf_sys::bpf_kprobe_multi_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_kprobe_multi_opts>() as libbpf_sys::size_t,
+            syms: if sym_ptrs.is_empty() {
+                ptr::null_mut()
+            } else {
+                sym_ptrs.as_mut_ptr()
+            },
+            addrs: if addresses.is_empty() {
+                ptr::null()
+            } else {
+                addresses.as_ptr() as *const _
+            },
+            cookies: if cookies.is_empty() {
+                ptr::null()
+            } else {
+                cookies.as_ptr()
+            },
+            cnt: cnt as libbpf_sys::size_t,
+            retprobe,
+            ..Default::default()
+        };
+
+        let pattern_ptr = pattern
+            .as_ref()
+            .map_or(ptr::null(), |pattern| pattern.as_ptr());
+        let ptr = unsafe {
+            libbpf_sys::bpf_program__attach_kprobe_multi_opts(
+                self.libbpf_ptr()?,
+                pattern_ptr,
+                &opts as *const _,
+            )
+        };
+        link_from_ptr(ptr)
+            .map_err(|err| {
+                // Kernels predating kprobe multi links reject them as invalid.
+                if err.errno() == Some(libc::EINVAL)
+                    && !kernel_supports_link_type("BPF_LINK_TYPE_KPROBE_MULTI")
+                {
+                    Error::System(libc::EOPNOTSUPP)
+                        .context("kernel lacks support for kprobe multi links")
+                } else {
+                    err
+                }
+            })
+            .with_context(|| match &pattern {
+                Some(pattern) => format!(
+                    "attach program `{}` to kprobes matching `{}`",
+                    self.name,
+                    pattern.to_string_lossy()
+                ),
+                None => format!("attach program `{}` to {} kprobes", self.name, cnt),
+            })
+    }
+
+    /// Attach this program to the given file `offsets` within `binary_path`
+    /// at once, using a single [`Link`]. See
+    /// [`Program::attach_uprobe_multi_with_opts()`].
+    pub fn attach_uprobe_multi<T: AsRef<Path>>(
+        &mut self,
+        retprobe: bool,
+        pid: i32,
+        binary_path: T,
+        offsets: &[usize],
+    ) -> Result<Link> {
+        self.attach_uprobe_multi_with_opts(
+            pid,
+            binary_path,
+            UprobeMultiOpts {
+                offsets: offsets.to_vec(),
+                retprobe,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Attach this program to multiple locations within the binary at
+    /// `binary_path` at once, using a single [`Link`].
+    ///
+    /// `pid` selects the process to attach to, with 0 meaning the calling
+    /// process and -1 meaning all processes.
+    ///
+    /// The program must have been loaded with
+    /// [`ProgramAttachType::TraceUprobeMulti`]. Uprobe multi links are
+    /// available as of Linux 6.6, and attaching fails with
+    /// [`ErrorKind::Unsupported`] on kernels lacking them, so that callers can
+    /// fall back to [`Program::attach_uprobe()`].
+    pub fn attach_uprobe_multi_with_opts<T: AsRef<Path>>(
+        &mut self,
+        pid: i32,
+        binary_path: T,
+        opts: UprobeMultiOpts,
+    ) -> Result<Link> {
+        let UprobeMultiOpts {
+            offsets,
+            ref_ctr_offsets,
+            cookies,
+            retprobe,
+            _non_exhaustive,
+        } = opts;
+
+        let cnt = offsets.len();
+        if cnt == 0 {
+            return Err(Error::InvalidInput(
+                "at least one offset must be provided".to_string(),
+            ));
+        }
+        if !ref_ctr_offsets.is_empty() && ref_ctr_offsets.len() != cnt {
+            return Err(Error::InvalidInput(format!(
+                "number of reference counter offsets ({}) does not match number of offsets ({})",
+                ref_ctr_offsets.len(),
+                cnt
+            )));
+        }
+        if !cookies.is_empty() && cookies.len() != cnt {
+            return Err(Error::InvalidInput(format!(
+                "number of cookies ({}) does not match number of offsets ({})",
+                cookies.len(),
+                cnt
+            )));
+        }
+        // Programs not opened from an object have no known attach type; let
+        // the kernel check those.
+        if !matches!(
+            self.attach_type(),
+            ProgramAttachType::TraceUprobeMulti | ProgramAttachType::Unknown
+        ) {
+            return Err(Error::InvalidInput(format!(
+                "program `{}` was not loaded with attach type {}",
+                self.name,
+                ProgramAttachType::TraceUprobeMulti
+            )));
+        }
+
+        let path = util::path_to_cstring(binary_path)?;
+        // Just like for the other uprobe attach methods, 0 refers to the
+        // calling process, whereas the kernel takes it to mean all processes.
+        let pid = match pid {
+            0 => unsafe { libc::getpid() as u32 },
+            pid if pid < 0 => 0,
+            pid => pid as u32,
+        };
+
+        let to_u64 = |offsets: &[usize]| {
+            offsets
+                .iter()
+                .map(|offset| *offset as u64)
+                .collect::<Vec<_>>()
+        };
+        let offsets = to_u64(&offsets);
+        let ref_ctr_offsets = to_u64(&ref_ctr_offsets);
+        let slice_ptr = |slice: &[u64]| {
+            if slice.is_empty() {
+                0
+            } else {
+                slice.as_ptr() as u64
+            }
+        };
+
+        let attr = bpf_uprobe_multi_link_create_attr {
+            prog_fd: self.fd as u32,
+            attach_type: ProgramAttachType::TraceUprobeMulti as u32,
+            path: path.as_ptr() as u64,
+            offsets: slice_ptr(&offsets),
+            ref_ctr_offsets: slice_ptr(&ref_ctr_offsets),
+            cookies: slice_ptr(&cookies),
+            cnt: u32::try_from(cnt)
+                .map_err(|_| Error::InvalidInput("too many offsets".to_string()))?,
+            uprobe_multi_flags: if retprobe {
+                BPF_F_UPROBE_MULTI_RETURN
+            } else {
+                0
+            },
+            pid,
+            ..Default::default()
+        };
+
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_bpf,
+                libbpf_sys::BPF_LINK_CREATE,
+                &attr as *const bpf_uprobe_multi_link_create_attr,
+                mem::size_of::<bpf_uprobe_multi_link_create_attr>() as u32,
+            )
+        };
+        let result = if fd < 0 {
+            let err = errno::errno();
+            // Kernels predating uprobe multi links reject them as invalid.
+            if err == libc::EINVAL && !kernel_supports_link_type("BPF_LINK_TYPE_UPROBE_MULTI") {
+                Err(Error::System(libc::EOPNOTSUPP)
+                    .context("kernel lacks support for uprobe multi links"))
+            } else {
+                Err(Error::System(err))
+            }
+        } else {
+            Ok(Link::from_fd(fd as i32))
+        };
+        result.with_context(|| {
+            format!(
+                "attach program `{}` to {} uprobes in `{}`",
+                self.name,
+                cnt,
+                path.to_string_lossy()
+            )
+        })
     }
 
     fn attach_tracepoint_impl(
@@ -535,7 +1365,7 @@ impl Program {
             let tp_opts = libbpf_sys::bpf_tracepoint_opts::from(tp_opts);
             unsafe {
                 libbpf_sys::bpf_program__attach_tracepoint_opts(
-                    self.ptr,
+                    self.libbpf_ptr()?,
                     tp_category_ptr,
                     tp_name_ptr,
                     &tp_opts as *const _,
@@ -543,16 +1373,22 @@ impl Program {
             }
         } else {
             unsafe {
-                libbpf_sys::bpf_program__attach_tracepoint(self.ptr, tp_category_ptr, tp_name_ptr)
+                libbpf_sys::bpf_program__attach_tracepoint(
+                    self.libbpf_ptr()?,
+                    tp_category_ptr,
+                    tp_name_ptr,
+                )
             }
         };
 
-        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
-        if err != 0 {
-            Err(Error::System(err as i32))
-        } else {
-            Ok(Link::new(ptr))
-        }
+        link_from_ptr(ptr).with_context(|| {
+            format!(
+                "attach program `{}` to tracepoint `{}:{}`",
+                self.name,
+                tp_category.to_string_lossy(),
+                tp_name.to_string_lossy()
+            )
+        })
     }
 
     /// Attach this program to a [kernel
@@ -582,35 +1418,74 @@ impl Program {
     pub fn attach_raw_tracepoint<T: AsRef<str>>(&mut self, tp_name: T) -> Result<Link> {
         let tp_name = util::str_to_cstring(tp_name.as_ref())?;
         let tp_name_ptr = tp_name.as_ptr();
-        let ptr = unsafe { libbpf_sys::bpf_program__attach_raw_tracepoint(self.ptr, tp_name_ptr) };
-        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
-        if err != 0 {
-            Err(Error::System(err as i32))
-        } else {
-            Ok(Link::new(ptr))
-        }
+        let ptr = unsafe {
+            libbpf_sys::bpf_program__attach_raw_tracepoint(self.libbpf_ptr()?, tp_name_ptr)
+        };
+        link_from_ptr(ptr).with_context(|| {
+            format!(
+                "attach program `{}` to raw tracepoint `{}`",
+                self.name,
+                tp_name.to_string_lossy()
+            )
+        })
+    }
+
+    /// Attach this [BPF iterator](https://docs.kernel.org/bpf/bpf_iterators.html)
+    /// program, restricting what it iterates over as per `opts`. Use
+    /// [`Program::attach()`] to iterate over everything.
+    pub fn attach_iter(&mut self, opts: IterOpts) -> Result<Link> {
+        let mut linfo = libbpf_sys::bpf_iter_link_info::from(opts);
+        let attach_opts = libbpf_sys::bpf_iter_attach_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_iter_attach_opts>() as libbpf_sys::size_t,
+            link_info: &mut linfo,
+            link_info_len: mem::size_of::<libbpf_sys::bpf_iter_link_info>() as u32,
+            ..Default::default()
+        };
+        let ptr = unsafe { libbpf_sys::bpf_program__attach_iter(self.libbpf_ptr()?, &attach_opts) };
+        link_from_ptr(ptr).with_context(|| format!("attach iterator program `{}`", self.name))
     }
 
     /// Attach to an [LSM](https://en.wikipedia.org/wiki/Linux_Security_Modules) hook
     pub fn attach_lsm(&mut self) -> Result<Link> {
-        let ptr = unsafe { libbpf_sys::bpf_program__attach_lsm(self.ptr) };
-        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
-        if err != 0 {
-            Err(Error::System(err as i32))
-        } else {
-            Ok(Link::new(ptr))
-        }
+        let ptr = unsafe { libbpf_sys::bpf_program__attach_lsm(self.libbpf_ptr()?) };
+        link_from_ptr(ptr).with_context(|| format!("attach program `{}` to LSM hook", self.name))
     }
 
     /// Attach to a [fentry/fexit kernel probe](https://lwn.net/Articles/801479/)
     pub fn attach_trace(&mut self) -> Result<Link> {
-        let ptr = unsafe { libbpf_sys::bpf_program__attach_trace(self.ptr) };
-        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
-        if err != 0 {
-            Err(Error::System(err as i32))
-        } else {
-            Ok(Link::new(ptr))
-        }
+        let ptr = unsafe { libbpf_sys::bpf_program__attach_trace(self.libbpf_ptr()?) };
+        link_from_ptr(ptr).with_context(|| format!("attach trace program `{}`", self.name))
+    }
+
+    /// Attach to a [fentry/fexit kernel probe](https://lwn.net/Articles/801479/),
+    /// providing additional options.
+    pub fn attach_trace_with_opts(&mut self, opts: TraceOpts) -> Result<Link> {
+        let opts = libbpf_sys::bpf_trace_opts::from(opts);
+        let ptr = unsafe { libbpf_sys::bpf_program__attach_trace_opts(self.libbpf_ptr()?, &opts) };
+        link_from_ptr(ptr).with_context(|| format!("attach trace program `{}`", self.name))
+    }
+
+    /// Attach this [`ProgramType::Ext`] program, replacing the global function
+    /// `func_name` of the already loaded program with fd `target_prog_fd`.
+    ///
+    /// The target may differ from the one set via
+    /// [`OpenProgram::set_attach_target()`], as long as the function's
+    /// signature matches. Dropping the link restores the original function.
+    pub fn attach_freplace(&mut self, target_prog_fd: i32, func_name: &str) -> Result<Link> {
+        let func_name_c = util::str_to_cstring(func_name)?;
+        let ptr = unsafe {
+            libbpf_sys::bpf_program__attach_freplace(
+                self.libbpf_ptr()?,
+                target_prog_fd,
+                func_name_c.as_ptr(),
+            )
+        };
+        link_from_ptr(ptr).with_context(|| {
+            format!(
+                "replace function `{}` of program with fd {} with program `{}`",
+                func_name, target_prog_fd, self.name
+            )
+        })
     }
 
     /// Attach a verdict/parser to a [sockmap/sockhash](https://lwn.net/Articles/731133/)
@@ -620,26 +1495,39 @@ impl Program {
         util::parse_ret(err)
     }
 
+    /// Attach this [`ProgramType::SocketFilter`] program to `socket`,
+    /// filtering the packets it receives. Any program previously attached is
+    /// replaced. Use [`detach_socket()`] to detach it again.
+    pub fn attach_socket(&self, socket: &impl AsRawFd) -> Result<()> {
+        set_socket_prog(socket.as_raw_fd(), libc::SO_ATTACH_BPF, self.fd)
+            .with_context(|| format!("attach program `{}` to socket", self.name))
+    }
+
+    /// Attach this [`ProgramType::SkReuseport`] program to the `SO_REUSEPORT`
+    /// group of `socket`, selecting the socket of the group to handle
+    /// incoming packets or connections. Any program previously attached to
+    /// the group is replaced. Use [`detach_reuseport()`] to detach it again.
+    pub fn attach_reuseport(&self, socket: &impl AsRawFd) -> Result<()> {
+        set_socket_prog(socket.as_raw_fd(), libc::SO_ATTACH_REUSEPORT_EBPF, self.fd)
+            .with_context(|| format!("attach program `{}` to reuseport group", self.name))
+    }
+
     /// Attach this program to [XDP](https://lwn.net/Articles/825998/)
     pub fn attach_xdp(&mut self, ifindex: i32) -> Result<Link> {
-        let ptr = unsafe { libbpf_sys::bpf_program__attach_xdp(self.ptr, ifindex) };
-        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
-        if err != 0 {
-            Err(Error::System(err as i32))
-        } else {
-            Ok(Link::new(ptr))
-        }
+        let ptr = unsafe { libbpf_sys::bpf_program__attach_xdp(self.libbpf_ptr()?, ifindex) };
+        link_from_ptr(ptr).with_context(|| {
+            format!(
+                "attach program `{}` to XDP on interface {}",
+                self.name, ifindex
+            )
+        })
     }
 
     /// Attach this program to [netns-based programs](https://lwn.net/Articles/819618/)
     pub fn attach_netns(&mut self, netns_fd: i32) -> Result<Link> {
-        let ptr = unsafe { libbpf_sys::bpf_program__attach_netns(self.ptr, netns_fd) };
-        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
-        if err != 0 {
-            Err(Error::System(err as i32))
-        } else {
-            Ok(Link::new(ptr))
-        }
+        let ptr = unsafe { libbpf_sys::bpf_program__attach_netns(self.libbpf_ptr()?, netns_fd) };
+        link_from_ptr(ptr)
+            .with_context(|| format!("attach program `{}` to network namespace", self.name))
     }
 
     fn attach_usdt_impl(
@@ -664,7 +1552,7 @@ impl Program {
 
         let ptr = unsafe {
             libbpf_sys::bpf_program__attach_usdt(
-                self.ptr,
+                self.libbpf_ptr()?,
                 pid,
                 path_ptr,
                 usdt_provider_ptr,
@@ -672,12 +1560,14 @@ impl Program {
                 usdt_opts_ptr,
             )
         };
-        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
-        if err != 0 {
-            Err(Error::System(err as i32))
-        } else {
-            Ok(Link::new(ptr))
-        }
+        link_from_ptr(ptr).with_context(|| {
+            format!(
+                "attach program `{}` to USDT `{}:{}`",
+                self.name,
+                usdt_provider.to_string_lossy(),
+                usdt_name.to_string_lossy()
+            )
+        })
     }
 
     /// Attach this program to a [USDT](https://lwn.net/Articles/753601/) probe
@@ -722,7 +1612,12 @@ impl Program {
     /// Returns the number of instructions that form the program.
     ///
     /// Please see note in [`OpenProgram::insn_cnt`].
+    ///
+    /// Always 0 for programs not belonging to an [`Object`].
     pub fn insn_cnt(&self) -> usize {
+        if self.ptr.is_null() {
+            return 0;
+        }
         unsafe { libbpf_sys::bpf_program__insn_cnt(self.ptr) as usize }
     }
 
@@ -730,9 +1625,227 @@ impl Program {
     ///
     /// Please see note in [`OpenProgram::insns`].
     ///
+    /// Always empty for programs not belonging to an [`Object`].
     pub fn insns(&self) -> &[libbpf_sys::bpf_insn] {
+        if self.ptr.is_null() {
+            return &[];
+        }
         let count = self.insn_cnt();
         let ptr = unsafe { libbpf_sys::bpf_program__insns(self.ptr) };
         unsafe { std::slice::from_raw_parts(ptr, count) }
     }
+
+    /// Run this program on the given input in the kernel, without attaching
+    /// it anywhere, e.g., to test it.
+    ///
+    /// Only supported for some program types, such as
+    /// [`ProgramType::Xdp`], [`ProgramType::SchedCls`],
+    /// [`ProgramType::SocketFilter`], [`ProgramType::RawTracepoint`], and
+    /// [`ProgramType::Syscall`].
+    pub fn test_run(&self, opts: TestRunOpts<'_>) -> Result<TestRunOutput> {
+        let TestRunOpts {
+            data_in,
+            data_out_size,
+            context_in,
+            context_out_size,
+            repeat,
+            flags,
+            cpu,
+            batch_size,
+            _non_exhaustive,
+        } = opts;
+
+        let mut data_out = vec![0; data_out_size];
+        let mut context_out = vec![0; context_out_size];
+        // libbpf expects null pointers for absent buffers.
+        let in_ptr = |buf: &[u8]| {
+            if buf.is_empty() {
+                ptr::null()
+            } else {
+                buf.as_ptr() as *const c_void
+            }
+        };
+        let out_ptr = |buf: &mut Vec<u8>| {
+            if buf.is_empty() {
+                ptr::null_mut()
+            } else {
+                buf.as_mut_ptr() as *mut c_void
+            }
+        };
+        let size = |len: usize| {
+            u32::try_from(len)
+                .map_err(|_| Error::InvalidInput(format!("buffer size {} exceeds u32::MAX", len)))
+        };
+
+        let mut opts = libbpf_sys::bpf_test_run_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_test_run_opts>() as libbpf_sys::size_t,
+            data_in: in_ptr(data_in),
+            data_out: out_ptr(&mut data_out),
+            data_size_in: size(data_in.len())?,
+            data_size_out: size(data_out.len())?,
+            ctx_in: in_ptr(context_in),
+            ctx_out: out_ptr(&mut context_out),
+            ctx_size_in: size(context_in.len())?,
+            ctx_size_out: size(context_out.len())?,
+            repeat: i32::try_from(repeat).map_err(|_| {
+                Error::InvalidInput(format!("repeat count {} exceeds i32::MAX", repeat))
+            })?,
+            flags,
+            cpu,
+            batch_size,
+            ..Default::default()
+        };
+
+        let ret = unsafe { libbpf_sys::bpf_prog_test_run_opts(self.fd, &mut opts) };
+        let () =
+            util::parse_ret(ret).with_context(|| format!("test run program `{}`", self.name))?;
+
+        // The kernel reports how much of the buffers it filled.
+        data_out.truncate(opts.data_size_out as usize);
+        context_out.truncate(opts.ctx_size_out as usize);
+
+        Ok(TestRunOutput {
+            return_value: opts.retval,
+            duration: Duration::from_nanos(opts.duration.into()),
+            data_out,
+            context_out,
+        })
+    }
+}
+
+impl Drop for Program {
+    fn drop(&mut self) {
+        // Programs belonging to an `Object` are closed along with it. All
+        // others are owned by us.
+        if self.ptr.is_null() {
+            let _ = unistd::close(self.fd);
+        }
+    }
+}
+
+/// Turn the result of one of libbpf's link creating functions into a [`Link`].
+fn link_from_ptr(ptr: *mut libbpf_sys::bpf_link) -> Result<Link> {
+    let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
+    if err != 0 {
+        Err(Error::System(err as i32))
+    } else {
+        Ok(Link::new(ptr))
+    }
+}
+
+/// The `BPF_LINK_CREATE` part of `union bpf_attr` for uprobe multi links,
+/// which libbpf v1.1 does not know about.
+#[allow(non_camel_case_types)]
+#[repr(C)]
+#[derive(Debug, Default)]
+struct bpf_uprobe_multi_link_create_attr {
+    prog_fd: u32,
+    target_fd: u32,
+    attach_type: u32,
+    flags: u32,
+    path: u64,
+    offsets: u64,
+    ref_ctr_offsets: u64,
+    cookies: u64,
+    cnt: u32,
+    uprobe_multi_flags: u32,
+    pid: u32,
+    _pad: u32,
+}
+
+/// `BPF_F_UPROBE_MULTI_RETURN`, not defined by libbpf v1.1.
+const BPF_F_UPROBE_MULTI_RETURN: u32 = 1;
+
+/// Check whether the running kernel supports links of the type with the
+/// given `name`, e.g., `BPF_LINK_TYPE_KPROBE_MULTI`, based on whether its BTF
+/// knows about them. Assume that it does if we can't tell.
+fn kernel_supports_link_type(name: &str) -> bool {
+    let btf = unsafe { libbpf_sys::btf__load_vmlinux_btf() };
+    if unsafe { libbpf_sys::libbpf_get_error(btf as *const _) } != 0 {
+        return true;
+    }
+
+    let supported = (|| {
+        let enum_name = util::str_to_cstring("bpf_link_type").ok()?;
+        let id = unsafe {
+            libbpf_sys::btf__find_by_name_kind(btf, enum_name.as_ptr(), libbpf_sys::BTF_KIND_ENUM)
+        };
+        let ty = unsafe { libbpf_sys::btf__type_by_id(btf, u32::try_from(id).ok()?).as_ref()? };
+        // The enum's values directly follow its type.
+        let values = unsafe {
+            slice::from_raw_parts(
+                (ty as *const libbpf_sys::btf_type).add(1) as *const libbpf_sys::btf_enum,
+                (ty.info & 0xffff) as usize,
+            )
+        };
+        Some(values.iter().any(|value| {
+            let value_name = unsafe { libbpf_sys::btf__name_by_offset(btf, value.name_off) };
+            !value_name.is_null()
+                && unsafe { CStr::from_ptr(value_name) }.to_bytes() == name.as_bytes()
+        }))
+    })();
+
+    unsafe { libbpf_sys::btf__free(btf) };
+    supported.unwrap_or(true)
+}
+
+/// Convert `attach_type` for use with `BPF_PROG_ATTACH` and `BPF_PROG_DETACH`.
+fn legacy_attach_type(attach_type: ProgramAttachType) -> Result<libbpf_sys::bpf_attach_type> {
+    match attach_type {
+        ProgramAttachType::Unknown => Err(Error::InvalidInput(
+            "cannot attach to or detach from unknown attach type".to_string(),
+        )),
+        ty => Ok(ty as libbpf_sys::bpf_attach_type),
+    }
+}
+
+/// Retrieve the kernel's information about the program referred to by `fd`.
+pub(crate) fn prog_info_by_fd(fd: i32) -> Result<libbpf_sys::bpf_prog_info> {
+    // Padding bytes need to be zero as well, so use `zeroed()` instead of
+    // `default()`. See `query` for details.
+    let mut info: libbpf_sys::bpf_prog_info = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libbpf_sys::bpf_prog_info>() as u32;
+    let ret = unsafe {
+        libbpf_sys::bpf_obj_get_info_by_fd(fd, &mut info as *mut _ as *mut c_void, &mut len)
+    };
+    util::parse_ret(ret)?;
+    Ok(info)
+}
+
+/// Detach the socket filter program attached to `socket`, e.g., via
+/// [`Program::attach_socket()`].
+///
+/// A socket has at most one such program, so whichever program is attached
+/// gets detached.
+pub fn detach_socket(socket: &impl AsRawFd) -> Result<()> {
+    set_socket_prog(socket.as_raw_fd(), libc::SO_DETACH_BPF, 0)
+        .context("detach program from socket")
+}
+
+/// Detach the program attached to the `SO_REUSEPORT` group of `socket`, e.g.,
+/// via [`Program::attach_reuseport()`].
+///
+/// A group has at most one such program, so whichever program is attached
+/// gets detached.
+pub fn detach_reuseport(socket: &impl AsRawFd) -> Result<()> {
+    set_socket_prog(socket.as_raw_fd(), SO_DETACH_REUSEPORT_BPF, 0)
+        .context("detach program from reuseport group")
+}
+
+/// Attach the program with fd `prog_fd` to `socket` via the socket option
+/// `opt`, or detach the program attached, ignoring `prog_fd`.
+fn set_socket_prog(socket: i32, opt: i32, prog_fd: i32) -> Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            socket,
+            libc::SOL_SOCKET,
+            opt,
+            &prog_fd as *const i32 as *const c_void,
+            mem::size_of::<i32>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::System(errno::errno()));
+    }
+    Ok(())
 }
@@ -14,10 +14,11 @@ use core::ffi::c_void;
 use std::convert::TryFrom;
 use std::mem::size_of;
 use std::os::raw::c_char;
+use std::ptr;
 use std::string::String;
 use std::time::Duration;
 
-use nix::{errno, unistd::close};
+use nix::{errno, libc, unistd::close};
 
 use crate::*;
 
@@ -85,7 +86,7 @@ macro_rules! gen_info_impl {
     };
 }
 
-fn name_arr_to_string(a: &[c_char], default: &str) -> String {
+pub(crate) fn name_arr_to_string(a: &[c_char], default: &str) -> String {
     let converted_arr: Vec<u8> = a
         .iter()
         .take_while(|x| **x != 0)
@@ -185,6 +186,24 @@ impl ProgramInfo {
             run_cnt: s.run_cnt,
         })
     }
+
+    /// Retrieve information about the loaded program with the given `id`.
+    pub fn from_id(id: u32) -> Result<Self> {
+        let fd = unsafe { libbpf_sys::bpf_prog_get_fd_by_id(id) };
+        let () = util::parse_ret(fd).with_context(|| format!("open program with id {}", id))?;
+
+        let info = program::prog_info_by_fd(fd)
+            .map(|info| Self::from_uapi(fd, info))
+            .with_context(|| format!("retrieve information about program with id {}", id));
+        let _ = close(fd);
+
+        info?.ok_or_else(|| {
+            Error::Internal(format!(
+                "failed to parse information about program with id {}",
+                id
+            ))
+        })
+    }
 }
 
 gen_info_impl!(
@@ -398,3 +417,137 @@ gen_info_impl!(
     libbpf_sys::bpf_link_get_next_id,
     libbpf_sys::bpf_link_get_fd_by_id
 );
+
+/// A program attached to a target, as reported by [`prog_query()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AttachedProgram {
+    /// The id of the program.
+    pub prog_id: u32,
+    /// The id of the link the program is attached through, if any. Only
+    /// reported by kernels supporting it and only for some attach types.
+    pub link_id: Option<u32>,
+    /// The `BPF_F_*` flags the program was attached with. Always zero for
+    /// effective queries.
+    pub attach_flags: u32,
+}
+
+impl AttachedProgram {
+    /// Retrieve information about the attached program.
+    pub fn prog_info(&self) -> Result<ProgramInfo> {
+        ProgramInfo::from_id(self.prog_id)
+    }
+}
+
+/// The programs attached to a target, as reported by [`prog_query()`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProgQueryInfo {
+    /// The programs attached, in the order they run.
+    pub progs: Vec<AttachedProgram>,
+    /// The `BPF_F_*` flags shared by all programs attached, for targets
+    /// reporting them.
+    pub attach_flags: u32,
+    /// The revision of the target's set of programs, which changes with every
+    /// attachment and detachment. Zero if not reported by the kernel for the
+    /// target.
+    pub revision: u64,
+}
+
+/// Query the programs attached to `target_fd` for `attach_type`.
+///
+/// The target is, e.g., a cgroup, network namespace, or sockmap file
+/// descriptor, depending on the attach type. If `effective` is set, the
+/// programs effectively run for the target are reported, including those
+/// inherited from parent cgroups.
+pub fn prog_query(
+    target_fd: i32,
+    attach_type: ProgramAttachType,
+    effective: bool,
+) -> Result<ProgQueryInfo> {
+    let mut opts = libbpf_sys::bpf_prog_query_opts {
+        sz: size_of::<libbpf_sys::bpf_prog_query_opts>() as libbpf_sys::size_t,
+        query_flags: if effective {
+            libbpf_sys::BPF_F_QUERY_EFFECTIVE
+        } else {
+            0
+        },
+        ..Default::default()
+    };
+    let attach_type = attach_type as u32;
+    let query = |opts: &mut libbpf_sys::bpf_prog_query_opts| {
+        let ret =
+            unsafe { libbpf_sys::bpf_prog_query_opts(target_fd, attach_type, opts as *mut _) };
+        util::parse_ret(ret).with_context(|| format!("query programs attached to fd {}", target_fd))
+    };
+    // Link ids are supported as of Linux 6.6 and per program flags as of
+    // Linux 6.0. Older kernels reject queries for them as invalid, in which
+    // case we stop asking for them. Per program flags cannot be queried for
+    // effective programs.
+    let mut query_link_ids = true;
+    let mut query_prog_attach_flags = !effective;
+
+    loop {
+        // Learn about the number of programs first, then retrieve them. The
+        // kernel reports ENOSPC if more got attached in between.
+        opts.prog_ids = ptr::null_mut();
+        opts.prog_attach_flags = ptr::null_mut();
+        opts.link_ids = ptr::null_mut();
+        opts.__bindgen_anon_1.count = 0;
+        let () = query(&mut opts)?;
+
+        let count = unsafe { opts.__bindgen_anon_1.count } as usize;
+        if count == 0 {
+            return Ok(ProgQueryInfo {
+                progs: Vec::new(),
+                attach_flags: opts.attach_flags,
+                revision: opts.revision,
+            });
+        }
+
+        let mut prog_ids = vec![0u32; count];
+        let mut prog_attach_flags = vec![0u32; count];
+        let mut link_ids = vec![0u32; count];
+        let result = loop {
+            opts.__bindgen_anon_1.count = count as u32;
+            opts.prog_ids = prog_ids.as_mut_ptr();
+            opts.prog_attach_flags = if query_prog_attach_flags {
+                prog_attach_flags.as_mut_ptr()
+            } else {
+                ptr::null_mut()
+            };
+            opts.link_ids = if query_link_ids {
+                link_ids.as_mut_ptr()
+            } else {
+                ptr::null_mut()
+            };
+
+            match query(&mut opts) {
+                Err(err) if err.errno() == Some(libc::EINVAL) && query_link_ids => {
+                    query_link_ids = false
+                }
+                Err(err) if err.errno() == Some(libc::EINVAL) && query_prog_attach_flags => {
+                    query_prog_attach_flags = false
+                }
+                result => break result,
+            }
+        };
+        match result {
+            Err(err) if err.errno() == Some(libc::ENOSPC) => continue,
+            result => result?,
+        }
+
+        let count = (unsafe { opts.__bindgen_anon_1.count } as usize).min(count);
+        let progs = (0..count)
+            .map(|i| AttachedProgram {
+                prog_id: prog_ids[i],
+                link_id: Some(link_ids[i]).filter(|id| *id != 0),
+                attach_flags: prog_attach_flags[i],
+            })
+            .collect();
+
+        return Ok(ProgQueryInfo {
+            progs,
+            attach_flags: opts.attach_flags,
+            revision: opts.revision,
+        });
+    }
+}
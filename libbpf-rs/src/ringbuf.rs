@@ -3,18 +3,39 @@ use std::boxed::Box;
 use std::fmt::Debug;
 use std::fmt::Formatter;
 use std::fmt::Result as FmtResult;
+use std::mem;
 use std::ops::Deref as _;
 use std::os::raw::c_ulong;
-use std::ptr;
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::Arc;
 use std::time::Duration;
 
+use nix::libc;
+use plain::Plain;
+
+use crate::typed_map::pod_from_bytes;
 use crate::*;
 
 type Cb<'a> = Box<dyn FnMut(&[u8]) -> i32 + 'a>;
 
+/// The value of a consumption budget that does not limit consumption.
+const UNLIMITED: usize = usize::MAX;
+/// What callbacks return to libbpf to stop consumption once the budget set by
+/// [`RingBuffer::consume_n()`] is exhausted. User callbacks may return the
+/// same value, which is told apart by the budget not having run out.
+const BUDGET_EXHAUSTED: i32 = -libc::ECANCELED;
+/// What the callback of a ringbuf channel returns to libbpf to stop
+/// consumption once the receiving side is gone or stopped.
+const CHANNEL_CLOSED: i32 = -libc::EPIPE;
+
 struct RingBufferCallback<'a> {
     cb: Cb<'a>,
+    /// The number of samples that may still be consumed, shared by all
+    /// callbacks of a [`RingBuffer`]. Set once the callback is added to one.
+    budget: Option<Arc<AtomicUsize>>,
 }
 
 impl<'a> RingBufferCallback<'a> {
@@ -22,19 +43,31 @@ impl<'a> RingBufferCallback<'a> {
     where
         F: FnMut(&[u8]) -> i32 + 'a,
     {
-        RingBufferCallback { cb: Box::new(cb) }
+        RingBufferCallback {
+            cb: Box::new(cb),
+            budget: None,
+        }
     }
 }
 
 impl Debug for RingBufferCallback<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        let Self { cb } = self;
+        let Self { cb, budget } = self;
         f.debug_struct("RingBufferCallback")
             .field("cb", &(cb.deref() as *const _))
+            .field("budget", budget)
             .finish()
     }
 }
 
+/// Check that `map` can be added to a [`RingBuffer`].
+fn check_ringbuf_map(map: &Map) -> Result<()> {
+    if map.map_type() != MapType::RingBuf {
+        return Err(Error::InvalidInput("Must use a RingBuf map".into()));
+    }
+    Ok(())
+}
+
 /// Builds [`RingBuffer`] instances.
 ///
 /// `ringbuf`s are a special kind of [`Map`], used to transfer data between
@@ -65,67 +98,273 @@ impl<'a> RingBufferBuilder<'a> {
     where
         NewF: FnMut(&[u8]) -> i32 + 'a,
     {
-        if map.map_type() != MapType::RingBuf {
-            return Err(Error::InvalidInput("Must use a RingBuf map".into()));
-        }
+        let () = check_ringbuf_map(map)?;
         self.fd_callbacks
             .push((map.fd(), RingBufferCallback::new(callback)));
         Ok(self)
     }
 
-    /// Build a new [`RingBuffer`]. Must have added at least one ringbuf.
-    pub fn build(self) -> Result<RingBuffer<'a>> {
-        let mut cbs = vec![];
-        let mut ptr: *mut libbpf_sys::ring_buffer = ptr::null_mut();
-        let c_sample_cb: libbpf_sys::ring_buffer_sample_fn = Some(Self::call_sample_cb);
-
-        for (fd, callback) in self.fd_callbacks {
-            let sample_cb_ptr = Box::into_raw(Box::new(callback));
-            if ptr.is_null() {
-                // Allocate a new ringbuf manager and add a ringbuf to it
-                ptr = unsafe {
-                    libbpf_sys::ring_buffer__new(
-                        fd,
-                        c_sample_cb,
-                        sample_cb_ptr as *mut _,
-                        std::ptr::null_mut(),
-                    )
-                };
-
-                // Handle errors
-                let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
-                if err != 0 {
-                    return Err(Error::System(err as i32));
-                }
+    /// Add a new ringbuf `map` whose events are delivered as owned buffers
+    /// through a channel holding up to `capacity` events, instead of being
+    /// passed to a callback.
+    ///
+    /// Events are sent while the [`RingBuffer`] is polled or consumed, and
+    /// can be received from any thread via the returned
+    /// [`RingBufferReceiver`]. `backpressure` determines what happens to
+    /// events arriving while the channel is full.
+    ///
+    /// Once the receiver has been dropped or [`RingBufferReceiver::stop()`]
+    /// has been called, polling or consuming the [`RingBuffer`] fails with
+    /// `EPIPE` as soon as an event of this ringbuf arrives. That event is
+    /// counted as dropped.
+    pub fn add_channel(
+        &mut self,
+        map: &Map,
+        capacity: usize,
+        backpressure: Backpressure,
+    ) -> Result<RingBufferReceiver<Vec<u8>>> {
+        self.add_channel_impl(map, capacity, backpressure, |data| Some(data.to_vec()))
+    }
+
+    /// Same as [`RingBufferBuilder::add_channel()`], except that events are
+    /// delivered as plain old data values of type `T`.
+    ///
+    /// Events whose size differs from that of `T` can't be delivered and are
+    /// counted as dropped.
+    pub fn add_channel_typed<T>(
+        &mut self,
+        map: &Map,
+        capacity: usize,
+        backpressure: Backpressure,
+    ) -> Result<RingBufferReceiver<T>>
+    where
+        T: Plain + Send + 'a,
+    {
+        self.add_channel_impl(map, capacity, backpressure, |data| {
+            if data.len() != mem::size_of::<T>() {
+                None
             } else {
-                // Add a ringbuf to the existing ringbuf manager
-                let err = unsafe {
-                    libbpf_sys::ring_buffer__add(ptr, fd, c_sample_cb, sample_cb_ptr as *mut _)
-                };
-
-                // Handle errors
-                if err != 0 {
-                    return Err(Error::System(err));
+                Some(pod_from_bytes(data))
+            }
+        })
+    }
+
+    fn add_channel_impl<T, F>(
+        &mut self,
+        map: &Map,
+        capacity: usize,
+        backpressure: Backpressure,
+        convert: F,
+    ) -> Result<RingBufferReceiver<T>>
+    where
+        T: Send + 'a,
+        F: Fn(&[u8]) -> Option<T> + 'a,
+    {
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let sender = ChannelSender {
+            tx,
+            backpressure,
+            dropped: dropped.clone(),
+            stopped: stopped.clone(),
+        };
+
+        let _ = self.add(map, move |data: &[u8]| {
+            if sender.stopped.load(Ordering::Relaxed) {
+                sender.drop_event();
+                return CHANNEL_CLOSED;
+            }
+
+            match convert(data) {
+                Some(event) => sender.send(event),
+                None => {
+                    sender.drop_event();
+                    0
                 }
             }
+        })?;
 
-            unsafe { cbs.push(Box::from_raw(sample_cb_ptr)) };
-        }
+        Ok(RingBufferReceiver {
+            rx,
+            dropped,
+            stopped,
+        })
+    }
 
-        if ptr.is_null() {
-            return Err(Error::InvalidInput(
+    /// Build a new [`RingBuffer`]. Must have added at least one ringbuf.
+    pub fn build(self) -> Result<RingBuffer<'a>> {
+        let mut fd_callbacks = self.fd_callbacks.into_iter();
+        let (fd, mut callback) = fd_callbacks.next().ok_or_else(|| {
+            Error::InvalidInput(
                 "You must add at least one ring buffer map and callback before building".into(),
-            ));
+            )
+        })?;
+
+        let budget = Arc::new(AtomicUsize::new(UNLIMITED));
+        callback.budget = Some(budget.clone());
+
+        // Allocate a new ringbuf manager and add a ringbuf to it
+        let sample_cb_ptr = Box::into_raw(Box::new(callback));
+        let ptr = unsafe {
+            libbpf_sys::ring_buffer__new(
+                fd,
+                Some(Self::call_sample_cb),
+                sample_cb_ptr as *mut _,
+                std::ptr::null_mut(),
+            )
+        };
+        let callback = unsafe { Box::from_raw(sample_cb_ptr) };
+
+        // Handle errors
+        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
+        if err != 0 {
+            return Err(Error::System(err as i32));
         }
 
-        Ok(RingBuffer { ptr, _cbs: cbs })
+        let mut ringbuf = RingBuffer {
+            ptr,
+            _cbs: vec![callback],
+            budget,
+        };
+        for (fd, callback) in fd_callbacks {
+            let () = ringbuf.add_impl(fd, callback)?;
+        }
+        Ok(ringbuf)
     }
 
     unsafe extern "C" fn call_sample_cb(ctx: *mut c_void, data: *mut c_void, size: c_ulong) -> i32 {
         let callback_struct = ctx as *mut RingBufferCallback;
         let callback = (*callback_struct).cb.as_mut();
 
-        callback(slice::from_raw_parts(data as *const u8, size as usize))
+        let ret = callback(slice::from_raw_parts(data as *const u8, size as usize));
+        if ret != 0 {
+            return ret;
+        }
+
+        // libbpf considers a sample consumed before invoking the callback, so
+        // bailing out here does not lose it.
+        match &(*callback_struct).budget {
+            Some(budget) => match budget.load(Ordering::Relaxed) {
+                UNLIMITED => 0,
+                1 => {
+                    budget.store(0, Ordering::Relaxed);
+                    BUDGET_EXHAUSTED
+                }
+                remaining => {
+                    budget.store(remaining - 1, Ordering::Relaxed);
+                    0
+                }
+            },
+            None => 0,
+        }
+    }
+}
+
+/// What to do with events arriving while the channel of a ringbuf added via
+/// [`RingBufferBuilder::add_channel()`] is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Drop the arriving event, counting it in
+    /// [`RingBufferReceiver::dropped()`].
+    DropNewest,
+    /// Block polling or consuming the [`RingBuffer`] until the receiver made
+    /// room for the event. The receiver must hence not be used on the thread
+    /// polling the [`RingBuffer`].
+    Block,
+}
+
+/// The sending side of a ringbuf channel, owned by the callback of the ringbuf.
+struct ChannelSender<T> {
+    tx: SyncSender<T>,
+    backpressure: Backpressure,
+    dropped: Arc<AtomicU64>,
+    /// Set by [`RingBufferReceiver::stop()`].
+    stopped: Arc<AtomicBool>,
+}
+
+impl<T> ChannelSender<T> {
+    /// Send `event`, returning what the ringbuf callback should return.
+    fn send(&self, event: T) -> i32 {
+        let closed = match self.backpressure {
+            Backpressure::DropNewest => match self.tx.try_send(event) {
+                Ok(()) => false,
+                Err(TrySendError::Full(_)) => {
+                    self.drop_event();
+                    false
+                }
+                Err(TrySendError::Disconnected(_)) => true,
+            },
+            Backpressure::Block => self.tx.send(event).is_err(),
+        };
+
+        if closed {
+            self.drop_event();
+            CHANNEL_CLOSED
+        } else {
+            0
+        }
+    }
+
+    fn drop_event(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// The receiving side of a ringbuf added via
+/// [`RingBufferBuilder::add_channel()`] or
+/// [`RingBufferBuilder::add_channel_typed()`].
+///
+/// Once the [`RingBuffer`] has been dropped, the remaining events can still
+/// be received, after which receiving fails.
+#[derive(Debug)]
+pub struct RingBufferReceiver<T> {
+    rx: mpsc::Receiver<T>,
+    dropped: Arc<AtomicU64>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl<T> RingBufferReceiver<T> {
+    /// Receive the next event, blocking until one is available.
+    ///
+    /// Returns `None` once the [`RingBuffer`] has been dropped and all
+    /// events have been received.
+    pub fn recv(&self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+
+    /// Receive the next event, blocking for at most `timeout`.
+    ///
+    /// Returns `None` on timeout, or once the [`RingBuffer`] has been dropped
+    /// and all events have been received.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<T> {
+        self.rx.recv_timeout(timeout).ok()
+    }
+
+    /// Receive the next event, if one is available right away.
+    pub fn try_recv(&self) -> Option<T> {
+        self.rx.try_recv().ok()
+    }
+
+    /// Returns an iterator blocking for each event, see
+    /// [`RingBufferReceiver::recv()`].
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        self.rx.iter()
+    }
+
+    /// Stop the delivery of events, making polling or consuming the
+    /// [`RingBuffer`] fail with `EPIPE` once the next event arrives, just as
+    /// if the receiver had been dropped. Events already sent can still be
+    /// received.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    /// The number of events that could not be delivered, because the
+    /// channel was full, the receiver was gone or stopped, or their size did
+    /// not match the event type.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
     }
 }
 
@@ -136,12 +375,53 @@ impl<'a> RingBufferBuilder<'a> {
 /// preferred over the `perf buffer`.
 #[derive(Debug)]
 pub struct RingBuffer<'a> {
-    ptr: *mut libbpf_sys::ring_buffer,
+    pub(crate) ptr: *mut libbpf_sys::ring_buffer,
     #[allow(clippy::vec_box)]
     _cbs: Vec<Box<RingBufferCallback<'a>>>,
+    budget: Arc<AtomicUsize>,
 }
 
 impl<'a> RingBuffer<'a> {
+    /// Add another ringbuf `map` and associated `callback` to this ring
+    /// buffer manager, after it has been built. See
+    /// [`RingBufferBuilder::add()`].
+    pub fn add<NewF>(&mut self, map: &Map, callback: NewF) -> Result<()>
+    where
+        NewF: FnMut(&[u8]) -> i32 + 'a,
+    {
+        let () = check_ringbuf_map(map)?;
+        self.add_impl(map.fd(), RingBufferCallback::new(callback))
+            .with_context(|| format!("add map `{}` to ring buffer", map.name()))
+    }
+
+    fn add_impl(&mut self, fd: i32, mut callback: RingBufferCallback<'a>) -> Result<()> {
+        callback.budget = Some(self.budget.clone());
+
+        let sample_cb_ptr = Box::into_raw(Box::new(callback));
+        let err = unsafe {
+            libbpf_sys::ring_buffer__add(
+                self.ptr,
+                fd,
+                Some(RingBufferBuilder::call_sample_cb),
+                sample_cb_ptr as *mut _,
+            )
+        };
+        // libbpf only references the callback if the ringbuf got added, but
+        // we have to take back ownership either way.
+        let callback = unsafe { Box::from_raw(sample_cb_ptr) };
+        if err != 0 {
+            return Err(Error::System(err));
+        }
+
+        self._cbs.push(callback);
+        Ok(())
+    }
+
+    /// Retrieve the number of ringbufs managed by this ring buffer.
+    pub fn ring_count(&self) -> usize {
+        self._cbs.len()
+    }
+
     /// Poll from all open ring buffers, calling the registered callback for
     /// each one. Polls continually until we either run out of events to consume
     /// or `timeout` is reached. If `timeout` is Duration::MAX, this will block
@@ -170,6 +450,34 @@ impl<'a> RingBuffer<'a> {
         util::parse_ret(ret)
     }
 
+    /// Consume at most `max` samples from all open ring buffers, calling the
+    /// registered callback for each one, and return the number of samples
+    /// consumed. Consumes continually until we run out of events to consume,
+    /// `max` samples have been consumed, or one of the callbacks returns a
+    /// non-zero integer.
+    ///
+    /// This allows for bounding the work done per call when events arrive
+    /// at a high rate.
+    pub fn consume_n(&self, max: usize) -> Result<usize> {
+        assert!(!self.ptr.is_null());
+
+        if max == 0 {
+            return Ok(0);
+        }
+
+        // `UNLIMITED` is reserved, but nobody will consume that many samples
+        // in one go anyway.
+        let max = max.min(UNLIMITED - 1);
+        self.budget.store(max, Ordering::Relaxed);
+        let ret = unsafe { libbpf_sys::ring_buffer__consume(self.ptr) };
+        let remaining = self.budget.swap(UNLIMITED, Ordering::Relaxed);
+
+        if !(remaining == 0 && ret == BUDGET_EXHAUSTED) {
+            let () = util::parse_ret(ret)?;
+        }
+        Ok(max - remaining)
+    }
+
     /// Get an fd that can be used to sleep until data is available
     pub fn epoll_fd(&self) -> i32 {
         assert!(!self.ptr.is_null());
@@ -178,6 +486,12 @@ impl<'a> RingBuffer<'a> {
     }
 }
 
+impl AsRawFd for RingBuffer<'_> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.epoll_fd()
+    }
+}
+
 // SAFETY: `ring_buffer` objects can safely be polled from any thread.
 unsafe impl Send for RingBuffer<'_> {}
 
@@ -0,0 +1,265 @@
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::ptr;
+
+use plain::Plain;
+
+use crate::*;
+
+/// Check that `K` and `V` match the key and value sizes of `map`.
+fn check_sizes<K, V>(map: &Map) -> Result<()> {
+    if map.key_size() as usize != size_of::<K>() {
+        return Err(Error::InvalidInput(format!(
+            "key_size {} != size of key type {}",
+            map.key_size(),
+            size_of::<K>()
+        )));
+    }
+
+    if map.value_size() as usize != size_of::<V>() {
+        return Err(Error::InvalidInput(format!(
+            "value_size {} != size of value type {}",
+            map.value_size(),
+            size_of::<V>()
+        )));
+    }
+
+    Ok(())
+}
+
+/// View a plain old data value as raw bytes.
+///
+/// # Safety
+/// `T` must not contain any padding bytes. `Plain` does not rule them out,
+/// but padding is uninitialized and must not be read as `u8`.
+pub(crate) unsafe fn pod_as_bytes<T: Plain>(val: &T) -> &[u8] {
+    // SAFETY: The caller guarantees that all bytes of `val` are initialized.
+    plain::as_bytes(val)
+}
+
+/// Read a plain old data value from the start of `bytes`.
+///
+/// `bytes` must have at least `size_of::<T>()` elements.
+pub(crate) fn pod_from_bytes<T: Plain>(bytes: &[u8]) -> T {
+    assert!(bytes.len() >= size_of::<T>());
+    // SAFETY: The buffer is large enough and any bit pattern is a valid
+    //         `Plain` value. `read_unaligned` copes with whatever
+    //         alignment the buffer happens to have.
+    unsafe { ptr::read_unaligned(bytes.as_ptr() as *const T) }
+}
+
+/// A typed view over a [`Map`] that is not of a per-cpu type.
+///
+/// Key and value sizes are checked against `K` and `V` once, when the view is
+/// created, after which keys and values are passed around as plain old data
+/// types instead of raw bytes. Types have to implement
+/// [`Plain`](https://docs.rs/plain/latest/plain/trait.Plain.html) and should
+/// be `#[repr(C)]` so that their layout matches what the BPF side expects.
+/// They must not contain padding, as their bytes are passed to the kernel as
+/// is.
+///
+/// Use [`TypedPercpuMap`] for per-cpu maps.
+pub struct TypedMap<'m, K, V> {
+    map: &'m mut Map,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<'m, K, V> TypedMap<'m, K, V>
+where
+    K: Plain,
+    V: Plain,
+{
+    /// Create a new `TypedMap` wrapping the provided `Map`.
+    ///
+    /// Fails if `map` is of a per-cpu type or if its key or value size does not
+    /// match the size of `K` or `V`, respectively.
+    ///
+    /// # Safety
+    /// Neither `K` nor `V` may contain padding bytes.
+    pub unsafe fn new(map: &'m mut Map) -> Result<Self> {
+        if map.map_type().is_percpu() {
+            return Err(Error::InvalidInput(format!(
+                "TypedPercpuMap must be used for per-cpu maps (type of the map is {})",
+                map.map_type(),
+            )));
+        }
+
+        let () = check_sizes::<K, V>(map)?;
+
+        Ok(Self {
+            map,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Retrieve the underlying [`Map`].
+    pub fn map(&self) -> &Map {
+        self.map
+    }
+
+    /// Look up the value stored for `key`, if any.
+    ///
+    /// See [`Map::lookup()`].
+    pub fn lookup(&self, key: &K, flags: MapFlags) -> Result<Option<V>> {
+        // SAFETY: `new()` requires `K` to be free of padding.
+        let value = self.map.lookup(unsafe { pod_as_bytes(key) }, flags)?;
+        Ok(value.map(|value| pod_from_bytes(&value)))
+    }
+
+    /// Update the value stored for `key`.
+    ///
+    /// See [`Map::update()`].
+    pub fn update(&mut self, key: &K, value: &V, flags: MapFlags) -> Result<()> {
+        // SAFETY: `new()` requires `K` and `V` to be free of padding.
+        let (key, value) = unsafe { (pod_as_bytes(key), pod_as_bytes(value)) };
+        self.map.update(key, value, flags)
+    }
+
+    /// Delete the element stored for `key`.
+    ///
+    /// See [`Map::delete()`].
+    pub fn delete(&mut self, key: &K) -> Result<()> {
+        // SAFETY: `new()` requires `K` to be free of padding.
+        self.map.delete(unsafe { pod_as_bytes(key) })
+    }
+
+    /// Same as [`TypedMap::lookup()`] except this also deletes the key from the map.
+    ///
+    /// See [`Map::lookup_and_delete()`].
+    pub fn lookup_and_delete(&mut self, key: &K) -> Result<Option<V>> {
+        // SAFETY: `new()` requires `K` to be free of padding.
+        let value = self.map.lookup_and_delete(unsafe { pod_as_bytes(key) })?;
+        Ok(value.map(|value| pod_from_bytes(&value)))
+    }
+
+    /// Returns an iterator over keys in this map.
+    ///
+    /// The same caveats as for [`Map::keys()`] apply.
+    pub fn keys(&self) -> impl Iterator<Item = K> + '_ {
+        self.map.keys().map(|key| pod_from_bytes(&key))
+    }
+
+    /// Returns an iterator over all `(key, value)` pairs in this map.
+    ///
+    /// See [`Map::entries()`].
+    pub fn entries(&self) -> Result<impl Iterator<Item = Result<(K, V)>> + '_> {
+        let iter = self.map.entries()?;
+        Ok(iter.map(|result| {
+            result.map(|(key, value)| (pod_from_bytes(&key), pod_from_bytes(&value)))
+        }))
+    }
+}
+
+impl<K, V> Debug for TypedMap<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let Self { map, _phantom } = self;
+        f.debug_struct("TypedMap").field("map", map).finish()
+    }
+}
+
+/// A typed view over a [`Map`] of a per-cpu type.
+///
+/// This is the per-cpu counterpart of [`TypedMap`]: every key is associated
+/// with one `V` per possible cpu (see [`num_possible_cpus()`]).
+pub struct TypedPercpuMap<'m, K, V> {
+    map: &'m mut Map,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<'m, K, V> TypedPercpuMap<'m, K, V>
+where
+    K: Plain,
+    V: Plain,
+{
+    /// Create a new `TypedPercpuMap` wrapping the provided `Map`.
+    ///
+    /// Fails if `map` is not of a per-cpu type or if its key or value size does
+    /// not match the size of `K` or `V`, respectively.
+    ///
+    /// # Safety
+    /// Neither `K` nor `V` may contain padding bytes.
+    pub unsafe fn new(map: &'m mut Map) -> Result<Self> {
+        if !map.map_type().is_percpu() {
+            return Err(Error::InvalidInput(format!(
+                "TypedMap must be used for maps that are not per-cpu (type of the map is {})",
+                map.map_type(),
+            )));
+        }
+
+        let () = check_sizes::<K, V>(map)?;
+
+        Ok(Self {
+            map,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Retrieve the underlying [`Map`].
+    pub fn map(&self) -> &Map {
+        self.map
+    }
+
+    /// Look up the values stored for `key`, if any, one per cpu.
+    ///
+    /// See [`Map::lookup_percpu()`].
+    pub fn lookup(&self, key: &K, flags: MapFlags) -> Result<Option<Vec<V>>> {
+        // SAFETY: `new()` requires `K` to be free of padding.
+        let values = self
+            .map
+            .lookup_percpu(unsafe { pod_as_bytes(key) }, flags)?;
+        Ok(values.map(|values| values.iter().map(|value| pod_from_bytes(value)).collect()))
+    }
+
+    /// Update the values stored for `key`.
+    ///
+    /// `values` must have one element per cpu (see [`num_possible_cpus()`]).
+    ///
+    /// See [`Map::update_percpu()`].
+    pub fn update(&mut self, key: &K, values: &[V], flags: MapFlags) -> Result<()> {
+        // SAFETY: `new()` requires `K` and `V` to be free of padding.
+        let values = values
+            .iter()
+            .map(|value| unsafe { pod_as_bytes(value) }.to_vec())
+            .collect::<Vec<_>>();
+        let key = unsafe { pod_as_bytes(key) };
+        self.map.update_percpu(key, &values, flags)
+    }
+
+    /// Delete the element stored for `key`.
+    ///
+    /// See [`Map::delete()`].
+    pub fn delete(&mut self, key: &K) -> Result<()> {
+        // SAFETY: `new()` requires `K` to be free of padding.
+        self.map.delete(unsafe { pod_as_bytes(key) })
+    }
+
+    /// Returns an iterator over keys in this map.
+    ///
+    /// The same caveats as for [`Map::keys()`] apply.
+    pub fn keys(&self) -> impl Iterator<Item = K> + '_ {
+        self.map.keys().map(|key| pod_from_bytes(&key))
+    }
+
+    /// Returns an iterator over all `(key, values)` pairs in this map.
+    ///
+    /// See [`Map::entries_percpu()`].
+    pub fn entries(&self) -> Result<impl Iterator<Item = Result<(K, Vec<V>)>> + '_> {
+        let iter = self.map.entries_percpu()?;
+        Ok(iter.map(|result| {
+            result.map(|(key, values)| {
+                let values = values.iter().map(|value| pod_from_bytes(value)).collect();
+                (pod_from_bytes(&key), values)
+            })
+        }))
+    }
+}
+
+impl<K, V> Debug for TypedPercpuMap<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let Self { map, _phantom } = self;
+        f.debug_struct("TypedPercpuMap").field("map", map).finish()
+    }
+}
@@ -0,0 +1,149 @@
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::os::raw::c_void;
+use std::slice;
+use std::time::Duration;
+
+use nix::errno;
+
+use crate::*;
+
+/// A ring buffer producing samples from user space for consumption by
+/// [`Program`]s, backed by a [`MapType::UserRingBuf`] [`Map`].
+///
+/// BPF programs drain the ring buffer via the `bpf_user_ringbuf_drain()`
+/// helper.
+///
+/// Reserving samples is not thread safe, which is why a `UserRingBuffer` can
+/// be sent to but not shared with other threads.
+#[derive(Debug)]
+pub struct UserRingBuffer {
+    ptr: *mut libbpf_sys::user_ring_buffer,
+}
+
+impl UserRingBuffer {
+    /// Create a new `UserRingBuffer` producing samples into `map`.
+    pub fn new(map: &Map) -> Result<Self> {
+        if map.map_type() != MapType::UserRingBuf {
+            return Err(Error::InvalidInput(format!(
+                "Invalid map type ({}) for UserRingBuffer",
+                map.map_type(),
+            )));
+        }
+
+        let opts = libbpf_sys::user_ring_buffer_opts {
+            sz: std::mem::size_of::<libbpf_sys::user_ring_buffer_opts>() as libbpf_sys::size_t,
+        };
+        let ptr = unsafe { libbpf_sys::user_ring_buffer__new(map.fd(), &opts) };
+        if ptr.is_null() {
+            return Err(Error::System(errno::errno())
+                .context(format!("create user ring buffer for map `{}`", map.name())));
+        }
+
+        Ok(Self { ptr })
+    }
+
+    /// Reserve a sample of `size` bytes.
+    ///
+    /// Fails with `ENOSPC` if the ring buffer currently lacks the space for
+    /// the sample and with `E2BIG` if it is too small to ever hold it.
+    pub fn reserve(&self, size: usize) -> Result<UserRingBufferSample<'_>> {
+        let size_u32 = sample_size(size)?;
+        let ptr = unsafe { libbpf_sys::user_ring_buffer__reserve(self.ptr, size_u32) };
+        self.sample_from_ptr(ptr, size)
+    }
+
+    /// Reserve a sample of `size` bytes, waiting for up to `timeout` for the
+    /// kernel to make room for it. If `timeout` is `Duration::MAX`, this will
+    /// block indefinitely.
+    pub fn reserve_blocking(
+        &self,
+        size: usize,
+        timeout: Duration,
+    ) -> Result<UserRingBufferSample<'_>> {
+        let size_u32 = sample_size(size)?;
+        let mut timeout_ms = -1;
+        if timeout != Duration::MAX {
+            timeout_ms = timeout.as_millis() as i32;
+        }
+
+        let ptr = unsafe {
+            libbpf_sys::user_ring_buffer__reserve_blocking(self.ptr, size_u32, timeout_ms)
+        };
+        self.sample_from_ptr(ptr, size)
+    }
+
+    fn sample_from_ptr(&self, ptr: *mut c_void, size: usize) -> Result<UserRingBufferSample<'_>> {
+        if ptr.is_null() {
+            return Err(Error::System(errno::errno()));
+        }
+
+        Ok(UserRingBufferSample {
+            rb: self,
+            ptr: ptr as *mut u8,
+            size,
+        })
+    }
+}
+
+/// Convert the size of a sample to what libbpf expects.
+fn sample_size(size: usize) -> Result<u32> {
+    u32::try_from(size)
+        .map_err(|_| Error::InvalidInput(format!("sample size {size} exceeds u32::MAX")))
+}
+
+// SAFETY: `user_ring_buffer` objects can be used from any thread, as long as
+//         reservations are not made concurrently.
+unsafe impl Send for UserRingBuffer {}
+
+impl Drop for UserRingBuffer {
+    fn drop(&mut self) {
+        unsafe { libbpf_sys::user_ring_buffer__free(self.ptr) }
+    }
+}
+
+/// A sample reserved in a [`UserRingBuffer`], dereferencing to its bytes.
+///
+/// The sample becomes visible to BPF programs only once it is submitted via
+/// [`UserRingBufferSample::submit()`]. Dropping it without doing so discards
+/// it.
+#[derive(Debug)]
+pub struct UserRingBufferSample<'rb> {
+    rb: &'rb UserRingBuffer,
+    ptr: *mut u8,
+    size: usize,
+}
+
+impl UserRingBufferSample<'_> {
+    /// Submit the sample, handing it over to the kernel.
+    pub fn submit(self) {
+        unsafe { libbpf_sys::user_ring_buffer__submit(self.rb.ptr, self.ptr as *mut c_void) };
+        // The sample is gone, so it must not be discarded as well.
+        std::mem::forget(self);
+    }
+
+    /// Discard the sample, releasing its space in the ring buffer.
+    ///
+    /// This is the same as dropping the sample.
+    pub fn discard(self) {}
+}
+
+impl Deref for UserRingBufferSample<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr, self.size) }
+    }
+}
+
+impl DerefMut for UserRingBufferSample<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.size) }
+    }
+}
+
+impl Drop for UserRingBufferSample<'_> {
+    fn drop(&mut self) {
+        unsafe { libbpf_sys::user_ring_buffer__discard(self.rb.ptr, self.ptr as *mut c_void) };
+    }
+}
@@ -1,7 +1,11 @@
 use std::ffi::{CStr, CString};
+use std::fmt;
+use std::fs;
 use std::os::raw::c_char;
 use std::path::Path;
 
+use nix::{libc, unistd};
+
 use crate::*;
 
 pub fn str_to_cstring(s: &str) -> Result<CString> {
@@ -28,6 +32,111 @@ pub fn c_ptr_to_string(p: *const c_char) -> Result<String> {
         .to_owned())
 }
 
+/// The kinds of BPF objects that can be opened from a pin.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BpfObjKind {
+    Map,
+    Program,
+}
+
+impl BpfObjKind {
+    /// The target of the `/proc/self/fd` link of an fd of this kind.
+    fn fd_link_target(self) -> &'static str {
+        match self {
+            BpfObjKind::Map => "anon_inode:bpf-map",
+            BpfObjKind::Program => "anon_inode:bpf-prog",
+        }
+    }
+}
+
+impl fmt::Display for BpfObjKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BpfObjKind::Map => f.write_str("map"),
+            BpfObjKind::Program => f.write_str("program"),
+        }
+    }
+}
+
+/// Open the BPF object pinned at `path`, checking that it is of the given
+/// `kind`, and return its fd.
+///
+/// The kernel happily reports information about a program for an fd
+/// referring to a map and vice versa, so this has to be checked up front.
+pub(crate) fn bpf_obj_get(path: &Path, kind: BpfObjKind) -> Result<i32> {
+    let path_c = path_to_cstring(path)?;
+    let fd = unsafe { libbpf_sys::bpf_obj_get(path_c.as_ptr()) };
+    let () = parse_ret(fd).with_context(|| format!("open pinned {} `{}`", kind, path.display()))?;
+
+    let result = match fs::read_link(format!("/proc/self/fd/{}", fd)) {
+        Ok(target) if target == Path::new(kind.fd_link_target()) => return Ok(fd),
+        Ok(_) => Err(Error::InvalidInput(format!(
+            "`{}` is not a pinned {}",
+            path.display(),
+            kind
+        ))),
+        // `/proc` may not be mounted, e.g., in minimal containers.
+        Err(_) => match fd_is_kind(fd, kind) {
+            Ok(true) => return Ok(fd),
+            Ok(false) => Err(Error::InvalidInput(format!(
+                "`{}` is not a pinned {}",
+                path.display(),
+                kind
+            ))),
+            Err(err) => Err(err.context(format!(
+                "determine kind of object pinned at `{}`",
+                path.display()
+            ))),
+        },
+    };
+    let _ = unistd::close(fd);
+    result
+}
+
+/// Check whether `fd` refers to a BPF object of the given `kind`, without
+/// relying on `/proc`.
+///
+/// Because the kernel reports information for any kind of object, look up the
+/// object of `kind` with the reported id and check that it matches.
+fn fd_is_kind(fd: i32, kind: BpfObjKind) -> Result<bool> {
+    match kind {
+        BpfObjKind::Map => {
+            let info = map::map_info_by_fd(fd)?;
+            let other_fd = unsafe { libbpf_sys::bpf_map_get_fd_by_id(info.id) };
+            if other_fd == -libc::ENOENT {
+                return Ok(false);
+            }
+            let () = parse_ret(other_fd)?;
+            let other = map::map_info_by_fd(other_fd);
+            let _ = unistd::close(other_fd);
+            let other = other?;
+
+            Ok(info.type_ == other.type_
+                && info.key_size == other.key_size
+                && info.value_size == other.value_size
+                && info.max_entries == other.max_entries
+                && info.map_flags == other.map_flags
+                && info.name == other.name)
+        }
+        BpfObjKind::Program => {
+            let info = program::prog_info_by_fd(fd)?;
+            let other_fd = unsafe { libbpf_sys::bpf_prog_get_fd_by_id(info.id) };
+            if other_fd == -libc::ENOENT {
+                return Ok(false);
+            }
+            let () = parse_ret(other_fd)?;
+            let other = program::prog_info_by_fd(other_fd);
+            let _ = unistd::close(other_fd);
+            let other = other?;
+
+            Ok(info.type_ == other.type_
+                && info.tag == other.tag
+                && info.load_time == other.load_time
+                && info.name == other.name)
+        }
+    }
+}
+
 /// Round up a number to the next multiple of `r`
 pub fn roundup(num: usize, r: usize) -> usize {
     ((num + (r - 1)) / r) * r
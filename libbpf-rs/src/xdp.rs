@@ -0,0 +1,141 @@
+use std::mem;
+use std::ptr;
+
+use bitflags::bitflags;
+
+use crate::*;
+
+bitflags! {
+    /// Flags to configure [`Xdp`] operations.
+    pub struct XdpFlags: u32 {
+        /// No flags.
+        const NONE              = 0;
+        /// See [`libbpf_sys::XDP_FLAGS_UPDATE_IF_NOEXIST`].
+        const UPDATE_IF_NOEXIST = libbpf_sys::XDP_FLAGS_UPDATE_IF_NOEXIST as _;
+        /// See [`libbpf_sys::XDP_FLAGS_SKB_MODE`].
+        const SKB_MODE          = libbpf_sys::XDP_FLAGS_SKB_MODE as _;
+        /// See [`libbpf_sys::XDP_FLAGS_DRV_MODE`].
+        const DRV_MODE          = libbpf_sys::XDP_FLAGS_DRV_MODE as _;
+        /// See [`libbpf_sys::XDP_FLAGS_HW_MODE`].
+        const HW_MODE           = libbpf_sys::XDP_FLAGS_HW_MODE as _;
+        /// See [`libbpf_sys::XDP_FLAGS_REPLACE`].
+        const REPLACE           = libbpf_sys::XDP_FLAGS_REPLACE as _;
+        /// See [`libbpf_sys::XDP_FLAGS_MODES`].
+        const MODES             = libbpf_sys::XDP_FLAGS_MODES as _;
+        /// See [`libbpf_sys::XDP_FLAGS_MASK`].
+        const MASK              = libbpf_sys::XDP_FLAGS_MASK as _;
+    }
+}
+
+/// The programs attached to a network interface via XDP, as retrieved via
+/// [`Xdp::query()`]. Ids are zero if no program is attached in the
+/// respective mode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct XdpQuery {
+    /// The id of the program attached in the only mode in use, if any.
+    pub prog_id: u32,
+    /// The id of the program attached in driver mode.
+    pub drv_prog_id: u32,
+    /// The id of the program attached in hardware mode.
+    pub hw_prog_id: u32,
+    /// The id of the program attached in generic, i.e., SKB, mode.
+    pub skb_prog_id: u32,
+    /// The `XDP_ATTACHED_*` mode programs are attached in.
+    pub attach_mode: u8,
+}
+
+/// Represents a [`Program`] to be attached to network interfaces via XDP
+/// using netlink, as opposed to via a [`Link`] as done by
+/// [`Program::attach_xdp()`].
+///
+/// Netlink based attachments outlive the process and allow for choosing the
+/// XDP mode as well as for atomically replacing a previously attached
+/// program.
+#[derive(Clone, Copy, Debug)]
+pub struct Xdp {
+    fd: i32,
+}
+
+impl Xdp {
+    /// Create a new [`Xdp`] given the file descriptor of a loaded XDP
+    /// [`Program`].
+    pub fn new(fd: i32) -> Self {
+        Self { fd }
+    }
+
+    /// Attach the program to the interface with index `ifindex`, in the mode
+    /// selected via `flags`.
+    pub fn attach(&self, ifindex: i32, flags: XdpFlags) -> Result<()> {
+        let ret = unsafe { libbpf_sys::bpf_xdp_attach(ifindex, self.fd, flags.bits, ptr::null()) };
+        util::parse_ret(ret).with_context(|| format!("attach XDP program to interface {}", ifindex))
+    }
+
+    /// Atomically replace the program with fd `old_prog_fd` attached to the
+    /// interface with index `ifindex` with this one. Fails with `EEXIST` if
+    /// another program is attached.
+    pub fn replace(&self, ifindex: i32, old_prog_fd: i32, flags: XdpFlags) -> Result<()> {
+        let opts = attach_opts(old_prog_fd);
+        let ret = unsafe {
+            libbpf_sys::bpf_xdp_attach(ifindex, self.fd, (flags | XdpFlags::REPLACE).bits, &opts)
+        };
+        util::parse_ret(ret)
+            .with_context(|| format!("replace XDP program on interface {}", ifindex))
+    }
+
+    /// Detach the program from the interface with index `ifindex`. Fails
+    /// with `EEXIST` if another program is attached.
+    pub fn detach(&self, ifindex: i32, flags: XdpFlags) -> Result<()> {
+        let opts = attach_opts(self.fd);
+        let ret =
+            unsafe { libbpf_sys::bpf_xdp_detach(ifindex, (flags | XdpFlags::REPLACE).bits, &opts) };
+        util::parse_ret(ret)
+            .with_context(|| format!("detach XDP program from interface {}", ifindex))
+    }
+
+    /// Detach whatever program is attached to the interface with index
+    /// `ifindex` in the mode selected via `flags`.
+    pub fn detach_any(ifindex: i32, flags: XdpFlags) -> Result<()> {
+        let ret = unsafe { libbpf_sys::bpf_xdp_detach(ifindex, flags.bits, ptr::null()) };
+        util::parse_ret(ret)
+            .with_context(|| format!("detach XDP program from interface {}", ifindex))
+    }
+
+    /// Query the programs attached to the interface with index `ifindex` in
+    /// any mode.
+    pub fn query(ifindex: i32, flags: XdpFlags) -> Result<XdpQuery> {
+        let mut opts = libbpf_sys::bpf_xdp_query_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_xdp_query_opts>() as libbpf_sys::size_t,
+            ..Default::default()
+        };
+        let ret = unsafe { libbpf_sys::bpf_xdp_query(ifindex, flags.bits as i32, &mut opts) };
+        let () = util::parse_ret(ret)
+            .with_context(|| format!("query XDP programs of interface {}", ifindex))?;
+
+        Ok(XdpQuery {
+            prog_id: opts.prog_id,
+            drv_prog_id: opts.drv_prog_id,
+            hw_prog_id: opts.hw_prog_id,
+            skb_prog_id: opts.skb_prog_id,
+            attach_mode: opts.attach_mode,
+        })
+    }
+
+    /// Query the id of the program attached to the interface with index
+    /// `ifindex` in the mode selected via `flags`, which is zero if there is
+    /// none.
+    pub fn query_id(ifindex: i32, flags: XdpFlags) -> Result<u32> {
+        let mut prog_id = 0;
+        let ret = unsafe { libbpf_sys::bpf_xdp_query_id(ifindex, flags.bits as i32, &mut prog_id) };
+        let () = util::parse_ret(ret)
+            .with_context(|| format!("query XDP program of interface {}", ifindex))?;
+        Ok(prog_id)
+    }
+}
+
+fn attach_opts(old_prog_fd: i32) -> libbpf_sys::bpf_xdp_attach_opts {
+    libbpf_sys::bpf_xdp_attach_opts {
+        sz: mem::size_of::<libbpf_sys::bpf_xdp_attach_opts>() as libbpf_sys::size_t,
+        old_prog_fd,
+        ..Default::default()
+    }
+}
@@ -1,22 +1,33 @@
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     fs,
-    io::Read,
+    io::{self, Read},
+    net::{Ipv4Addr, UdpSocket},
+    os::unix::io::{AsRawFd, FromRawFd},
     path::{Path, PathBuf},
     sync::mpsc::channel,
     time::Duration,
 };
 
 use nix::errno;
+use nix::sys::socket::{
+    bind, setsockopt, socket, sockopt, AddressFamily, SockFlag, SockType, SockaddrIn,
+};
 use plain::Plain;
 use probe::probe;
 use scopeguard::defer;
 use tempfile::NamedTempFile;
 
 use libbpf_rs::{
-    num_possible_cpus, Iter, Linker, Map, MapFlags, MapType, Object, ObjectBuilder, OpenObject,
-    ProgramType, TracepointOpts, UprobeOpts, UsdtOpts,
+    detach_reuseport, detach_socket, num_possible_cpus, query, Backpressure, CgroupAttachOpts,
+    Error, ErrorKind, Ipv4LpmKey, Ipv6LpmKey, Iter, IterOpts, KprobeMultiOpts, KprobeOpts, Linker,
+    LpmKey, LpmTrie, Map, MapFlags, MapType, Object, ObjectBuilder, OpenObject, PerfBufferBuilder,
+    PerfEvent, PerfEventBuilder, ProbeAttachMode, Program, ProgramAttachType, ProgramType,
+    TestRunOpts, TraceOpts, TracepointOpts, TypedMap, TypedPercpuMap, UprobeMultiOpts, UprobeOpts,
+    UsdtOpts, UserRingBuffer,
 };
+#[cfg(feature = "async")]
+use libbpf_rs::{AsyncPerfBuffer, AsyncRingBuffer};
 
 fn get_test_object_path(filename: &str) -> PathBuf {
     let mut path = PathBuf::new();
@@ -115,6 +126,53 @@ fn test_object_load_invalid() {
         .unwrap_err();
 }
 
+/// Load tracepoint.bpf.o with a program the verifier rejects, opening it
+/// with `builder`, and return the result.
+fn load_rejected_object(builder: &mut ObjectBuilder, log_level: u32) -> libbpf_rs::Result<Object> {
+    let obj_path = get_test_object_path("tracepoint.bpf.o");
+    let mut open_obj = builder.open_file(obj_path).expect("failed to open object");
+    let prog = open_obj
+        .prog_mut("handle__tracepoint_with_cookie")
+        .expect("failed to find program");
+    assert_eq!(prog.log_level(), log_level);
+    // Socket filters can't use `bpf_get_attach_cookie`, so the verifier
+    // rejects the program.
+    prog.set_prog_type(ProgramType::SocketFilter);
+    open_obj.load()
+}
+
+/// Check that the verifier log is reported when loading the rejected program
+/// opened with `builder`.
+fn check_verifier_log(builder: &mut ObjectBuilder, log_level: u32) {
+    match load_rejected_object(builder, log_level) {
+        Err(Error::ProgramLoad { name, errno, log }) => {
+            assert_eq!(name, "handle__tracepoint_with_cookie");
+            assert_ne!(errno, 0);
+            assert!(!log.is_empty());
+        }
+        res => panic!("unexpected load result: {res:?}"),
+    }
+}
+
+/// Check that the verifier log of a rejected program is reported.
+#[test]
+fn test_object_load_verifier_log() {
+    bump_rlimit_mlock();
+
+    // By default, no log is requested.
+    match load_rejected_object(&mut ObjectBuilder::default(), 0) {
+        Err(Error::System(errno)) => assert_ne!(errno, 0),
+        res => panic!("unexpected load result: {res:?}"),
+    }
+    // With a log size but no log level, the log is only requested once
+    // loading failed.
+    check_verifier_log(ObjectBuilder::default().log_size(1 << 16), 0);
+    // With logging enabled, the log ends up in the buffer provided.
+    check_verifier_log(ObjectBuilder::default().log_level(1).log_size(1 << 20), 1);
+    // Without a log size, a default sized buffer is used.
+    check_verifier_log(ObjectBuilder::default().log_level(1), 1);
+}
+
 #[test]
 fn test_object_name() {
     let obj_path = get_test_object_path("runqslower.bpf.o");
@@ -309,6 +367,70 @@ fn test_object_map_lookup_flags() {
         .is_err());
 }
 
+#[test]
+fn test_object_typed_map() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let start = obj.map_mut("start").expect("failed to find map");
+
+    assert!(unsafe { TypedMap::<u64, u64>::new(start) }.is_err());
+    assert!(unsafe { TypedMap::<u32, u32>::new(start) }.is_err());
+    assert!(unsafe { TypedPercpuMap::<u32, u64>::new(start) }.is_err());
+
+    let mut map = unsafe { TypedMap::<u32, u64>::new(start) }.expect("failed to create typed map");
+    assert!(map
+        .lookup(&42, MapFlags::ANY)
+        .expect("failed to lookup")
+        .is_none());
+
+    map.update(&42, &1337, MapFlags::NO_EXIST)
+        .expect("failed to write");
+    map.update(&43, &1338, MapFlags::NO_EXIST)
+        .expect("failed to write");
+    assert_eq!(
+        map.lookup(&42, MapFlags::ANY)
+            .expect("failed to lookup")
+            .expect("failed to find value for key"),
+        1337
+    );
+
+    let keys = map.keys().collect::<HashSet<_>>();
+    assert_eq!(keys, HashSet::from([42, 43]));
+
+    map.delete(&42).expect("failed to delete key");
+    assert!(map
+        .lookup(&42, MapFlags::ANY)
+        .expect("failed to lookup")
+        .is_none());
+    assert_eq!(map.keys().collect::<Vec<_>>(), vec![43]);
+}
+
+#[test]
+fn test_object_typed_percpu_map() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("percpu_map.bpf.o");
+    let percpu_map = obj.map_mut("percpu_map").expect("failed to find map");
+
+    assert!(unsafe { TypedMap::<u32, u32>::new(percpu_map) }.is_err());
+    assert!(unsafe { TypedPercpuMap::<u32, u64>::new(percpu_map) }.is_err());
+
+    let mut map =
+        unsafe { TypedPercpuMap::<u32, u32>::new(percpu_map) }.expect("failed to create typed map");
+    let vals = (0..num_possible_cpus().unwrap() as u32).collect::<Vec<_>>();
+
+    map.update(&0, &vals, MapFlags::ANY)
+        .expect("failed to update map");
+    assert!(map.update(&0, &vals[1..], MapFlags::ANY).is_err());
+
+    let res = map
+        .lookup(&0, MapFlags::ANY)
+        .expect("failed to lookup")
+        .expect("failed to find value for key");
+    assert_eq!(vals, res);
+}
+
 #[test]
 fn test_object_map_key_iter() {
     bump_rlimit_mlock();
@@ -691,6 +813,39 @@ fn test_object_task_iter() {
     assert!(items.iter().any(|&item| item.pid == 1));
 }
 
+/// Check that we can restrict a task iterator to a process and read its
+/// output as typed records.
+#[test]
+fn test_object_task_iter_records() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("taskiter.bpf.o");
+    let prog = obj.prog_mut("dump_pid").expect("Failed to find program");
+    let pid = std::process::id();
+    let link = prog
+        .attach_iter(IterOpts::Pid(pid))
+        .expect("Failed to attach prog");
+    let mut iter = Iter::new(&link).expect("Failed to create iterator");
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct IndexPidPair {
+        i: u32,
+        pid: i32,
+    }
+
+    unsafe impl Plain for IndexPidPair {}
+
+    let items = iter
+        .records::<IndexPidPair>()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("Failed to read from iterator");
+
+    assert!(!items.is_empty());
+    assert!(items.windows(2).all(|w| w[0].i + 1 == w[1].i));
+    assert!(items.iter().all(|item| item.pid == pid as i32));
+}
+
 #[test]
 fn test_object_map_create_and_pin() {
     bump_rlimit_mlock();
@@ -751,6 +906,7 @@ fn test_object_map_create_without_name() {
         map_extra: 0,
         numa_node: 0,
         map_ifindex: 0,
+        ..Default::default()
     };
 
     let mut map = Map::create(MapType::Hash, Option::<&str>::None, 4, 8, 8, &opts)
@@ -769,166 +925,1543 @@ fn test_object_map_create_without_name() {
     assert_eq!(val, res);
 }
 
+/// Create a standalone map with 4 byte keys and 8 byte values for testing.
+fn create_test_map(map_type: MapType, max_entries: u32) -> Map {
+    let opts = libbpf_sys::bpf_map_create_opts {
+        sz: std::mem::size_of::<libbpf_sys::bpf_map_create_opts>() as libbpf_sys::size_t,
+        ..Default::default()
+    };
+
+    Map::create(map_type, Option::<&str>::None, 4, 8, max_entries, &opts)
+        .expect("failed to create map")
+}
+
 #[test]
-fn test_object_usdt() {
+fn test_object_map_batch() {
     bump_rlimit_mlock();
 
-    let mut obj = get_test_object("usdt.bpf.o");
-    let prog = obj
-        .prog_mut("handle__usdt")
-        .expect("Failed to find program");
+    let mut map = create_test_map(MapType::Hash, 64);
 
-    let path = std::env::current_exe().expect("Failed to find executable name");
-    let _link = prog
-        .attach_usdt(
-            unsafe { libc::getpid() },
-            &path,
-            "test_provider",
-            "test_function",
-        )
-        .expect("Failed to attach prog");
+    let keys = (0..50u32).flat_map(u32::to_ne_bytes).collect::<Vec<_>>();
+    let values = (0..50u64)
+        .flat_map(|i| (i * 10).to_ne_bytes())
+        .collect::<Vec<_>>();
 
-    let map = obj.map("ringbuf").expect("Failed to get ringbuf map");
-    let action = || {
-        // Define a USDT probe point and exercise it as we are attaching to self.
-        probe!(test_provider, test_function, 1);
-    };
-    let result = with_ringbuffer(map, action);
+    assert!(map
+        .update_batch(&keys[1..], &values, MapFlags::ANY, MapFlags::ANY)
+        .is_err());
+    assert!(map
+        .update_batch(&keys, &values[8..], MapFlags::ANY, MapFlags::ANY)
+        .is_err());
+    assert!(map
+        .update_batch_percpu(&keys, &[], MapFlags::ANY, MapFlags::ANY)
+        .is_err());
+    map.update_batch(&keys, &values, MapFlags::ANY, MapFlags::ANY)
+        .expect("failed to update batch");
 
-    assert_eq!(result, 1);
+    assert!(map.lookup_batch(0, MapFlags::ANY, MapFlags::ANY).is_err());
+    assert!(map
+        .lookup_batch_percpu(4, MapFlags::ANY, MapFlags::ANY)
+        .is_err());
+
+    // Use a batch size that does not evenly divide the number of elements.
+    let elems = map
+        .lookup_batch(7, MapFlags::ANY, MapFlags::ANY)
+        .expect("failed to lookup batch")
+        .map(|elem| {
+            let (key, value) = elem.expect("failed to lookup batch");
+            let key = u32::from_ne_bytes(key.try_into().unwrap());
+            let value = u64::from_ne_bytes(value.try_into().unwrap());
+            (key, value)
+        })
+        .collect::<HashMap<_, _>>();
+    assert_eq!(elems.len(), 50);
+    assert!(elems.iter().all(|(k, v)| u64::from(*k) * 10 == *v));
+
+    let deleted = (0..20u32).flat_map(u32::to_ne_bytes).collect::<Vec<_>>();
+    map.delete_batch(&deleted, MapFlags::ANY, MapFlags::ANY)
+        .expect("failed to delete batch");
+    assert_eq!(map.keys().count(), 30);
+
+    let count = map
+        .lookup_and_delete_batch(8, MapFlags::ANY, MapFlags::ANY)
+        .expect("failed to lookup and delete batch")
+        .collect::<Result<Vec<_>, _>>()
+        .expect("failed to lookup and delete batch")
+        .len();
+    assert_eq!(count, 30);
+    assert_eq!(map.keys().count(), 0);
+
+    // Batched lookup on an empty map just yields nothing.
+    assert_eq!(
+        map.lookup_batch(4, MapFlags::ANY, MapFlags::ANY)
+            .expect("failed to lookup batch")
+            .count(),
+        0
+    );
 }
 
 #[test]
-fn test_object_usdt_cookie() {
+fn test_object_map_batch_percpu() {
     bump_rlimit_mlock();
 
-    let cookie_val = 1337u16;
-    let mut obj = get_test_object("usdt.bpf.o");
-    let prog = obj
-        .prog_mut("handle__usdt_with_cookie")
-        .expect("Failed to find program");
+    let mut map = create_test_map(MapType::PercpuHash, 16);
+    let ncpu = num_possible_cpus().unwrap();
+
+    let keys = (0..10u32).flat_map(u32::to_ne_bytes).collect::<Vec<_>>();
+    let values = (0..10u32)
+        .map(|i| {
+            (0..ncpu)
+                .map(|cpu| (u64::from(i) + cpu as u64).to_ne_bytes().to_vec())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let flat_values = vec![0; 10 * 8];
+    assert!(map
+        .update_batch(&keys, &flat_values, MapFlags::ANY, MapFlags::ANY)
+        .is_err());
+    assert!(map
+        .update_batch_percpu(&keys, &values[1..], MapFlags::ANY, MapFlags::ANY)
+        .is_err());
+    let short_values = values
+        .iter()
+        .map(|vals| vals[1..].to_vec())
+        .collect::<Vec<_>>();
+    assert!(map
+        .update_batch_percpu(&keys, &short_values, MapFlags::ANY, MapFlags::ANY)
+        .is_err());
+    map.update_batch_percpu(&keys, &values, MapFlags::ANY, MapFlags::ANY)
+        .expect("failed to update batch");
+
+    assert!(map.lookup_batch(4, MapFlags::ANY, MapFlags::ANY).is_err());
+
+    let elems = map
+        .lookup_batch_percpu(3, MapFlags::ANY, MapFlags::ANY)
+        .expect("failed to lookup batch")
+        .collect::<Result<Vec<_>, _>>()
+        .expect("failed to lookup batch");
+    assert_eq!(elems.len(), 10);
+
+    for (key, vals) in elems {
+        let key = u32::from_ne_bytes(key.try_into().unwrap());
+        assert_eq!(vals.len(), ncpu);
+        for (cpu, val) in vals.into_iter().enumerate() {
+            let val = u64::from_ne_bytes(val.try_into().unwrap());
+            assert_eq!(val, u64::from(key) + cpu as u64);
+        }
+    }
+}
 
-    let path = std::env::current_exe().expect("Failed to find executable name");
-    let _link = prog
-        .attach_usdt_with_opts(
-            unsafe { libc::getpid() },
-            &path,
-            "test_provider",
-            "test_function",
-            UsdtOpts {
-                cookie: cookie_val.into(),
-                ..UsdtOpts::default()
-            },
+#[test]
+fn test_object_map_entries() {
+    bump_rlimit_mlock();
+
+    let mut map = create_test_map(MapType::Hash, 128);
+    assert_eq!(map.entries().expect("failed to iterate entries").count(), 0);
+    assert!(map.entries_percpu().is_err());
+
+    for i in 0..100u32 {
+        map.update(
+            &i.to_ne_bytes(),
+            &u64::from(i * 2).to_ne_bytes(),
+            MapFlags::ANY,
         )
-        .expect("Failed to attach prog");
+        .expect("failed to write");
+    }
 
-    let map = obj.map("ringbuf").expect("Failed to get ringbuf map");
-    let action = || {
-        // Define a USDT probe point and exercise it as we are attaching to self.
-        probe!(test_provider, test_function, 1);
-    };
-    let result = with_ringbuffer(map, action);
+    let entries = map
+        .entries()
+        .expect("failed to iterate entries")
+        .collect::<Result<HashMap<_, _>, _>>()
+        .expect("failed to iterate entries");
+    assert_eq!(entries.len(), 100);
+    for i in 0..100u32 {
+        assert_eq!(
+            entries[&i.to_ne_bytes().to_vec()],
+            u64::from(i * 2).to_ne_bytes().to_vec()
+        );
+    }
 
-    assert_eq!(result, cookie_val.into());
+    let map = unsafe { TypedMap::<u32, u64>::new(&mut map) }.expect("failed to create typed map");
+    let entries = map
+        .entries()
+        .expect("failed to iterate entries")
+        .collect::<Result<HashMap<_, _>, _>>()
+        .expect("failed to iterate entries");
+    assert_eq!(entries.len(), 100);
+    assert!(entries.iter().all(|(k, v)| u64::from(*k) * 2 == *v));
 }
 
 #[test]
-fn test_object_map_probes() {
+fn test_object_map_entries_percpu() {
     bump_rlimit_mlock();
 
-    let supported = MapType::Array
-        .is_supported()
-        .expect("Failed to query if Array map is supported");
-    assert!(supported);
-    let supported_res = MapType::Unknown.is_supported();
-    assert!(supported_res.is_err());
+    let mut obj = get_test_object("percpu_map.bpf.o");
+    let map = obj.map_mut("percpu_map").expect("failed to find map");
+    assert!(map.entries().is_err());
+
+    let ncpu = num_possible_cpus().unwrap();
+    let vals = (0..ncpu as u32)
+        .map(|i| i.to_ne_bytes().to_vec())
+        .collect::<Vec<_>>();
+    map.update_percpu(&(0_u32).to_ne_bytes(), &vals, MapFlags::ANY)
+        .expect("failed to update map");
+
+    let entries = map
+        .entries_percpu()
+        .expect("failed to iterate entries")
+        .collect::<Result<HashMap<_, _>, _>>()
+        .expect("failed to iterate entries");
+    assert_eq!(entries[&(0_u32).to_ne_bytes().to_vec()], vals);
 }
 
 #[test]
-fn test_object_program_probes() {
+fn test_object_map_mmap() {
     bump_rlimit_mlock();
 
-    let supported = ProgramType::SocketFilter
-        .is_supported()
-        .expect("Failed to query if SocketFilter program is supported");
-    assert!(supported);
-    let supported_res = ProgramType::Unknown.is_supported();
-    assert!(supported_res.is_err());
+    let opts = libbpf_sys::bpf_map_create_opts {
+        sz: std::mem::size_of::<libbpf_sys::bpf_map_create_opts>() as libbpf_sys::size_t,
+        map_flags: libbpf_sys::BPF_F_MMAPABLE,
+        ..Default::default()
+    };
+    let mut map = Map::create(MapType::Array, Option::<&str>::None, 4, 8, 4, &opts)
+        .expect("failed to create map");
+
+    map.update(
+        &(1_u32).to_ne_bytes(),
+        &(42_u64).to_ne_bytes(),
+        MapFlags::ANY,
+    )
+    .expect("failed to write");
+
+    {
+        let mmap = map.mmap().expect("failed to mmap map");
+        assert_eq!(mmap.len(), 4 * 8);
+        assert!(!mmap.as_ptr().is_null());
+        assert_eq!(mmap.value(1).unwrap(), (42_u64).to_ne_bytes());
+        assert!(mmap.value(4).is_err());
+        assert_eq!(mmap.read::<u64>(1).unwrap(), 42);
+        assert_eq!(mmap.read::<u64>(2).unwrap(), 0);
+        assert!(mmap.read::<u32>(1).is_err());
+    }
+
+    {
+        let mut mmap = map.mmap_mut().expect("failed to mmap map");
+        mmap.write::<u64>(2, 1337).unwrap();
+        mmap.set_value(3, &(7_u64).to_ne_bytes()).unwrap();
+        assert!(mmap.set_value(3, &(7_u32).to_ne_bytes()).is_err());
+        assert!(mmap.write::<u64>(4, 0).is_err());
+    }
+
+    let val = map
+        .lookup(&(2_u32).to_ne_bytes(), MapFlags::ANY)
+        .expect("failed to lookup")
+        .expect("failed to find value for key");
+    assert_eq!(val, (1337_u64).to_ne_bytes());
+    let val = map
+        .lookup(&(3_u32).to_ne_bytes(), MapFlags::ANY)
+        .expect("failed to lookup")
+        .expect("failed to find value for key");
+    assert_eq!(val, (7_u64).to_ne_bytes());
+
+    // Frozen maps can only be mapped read-only.
+    map.freeze().expect("failed to freeze map");
+    assert!(map.mmap_mut().is_err());
+    let mmap = map.mmap().expect("failed to mmap map");
+    let vals = (0..4)
+        .map(|i| mmap.read::<u64>(i).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(vals, [0, 42, 1337, 7]);
 }
 
 #[test]
-fn test_object_program_helper_probes() {
+fn test_object_map_mmap_invalid() {
     bump_rlimit_mlock();
 
-    let supported = ProgramType::SocketFilter
-        .is_helper_supported(libbpf_sys::BPF_FUNC_map_lookup_elem)
-        .expect("Failed to query if helper supported");
-    assert!(supported);
-    // redirect should not be supported from socket filter, as it is only used in TC/XDP.
-    let supported = ProgramType::SocketFilter
-        .is_helper_supported(libbpf_sys::BPF_FUNC_redirect)
-        .expect("Failed to query if helper supported");
-    assert!(!supported);
-    let supported_res = MapType::Unknown.is_supported();
-    assert!(supported_res.is_err());
+    let map = create_test_map(MapType::Hash, 4);
+    assert!(map.mmap().is_err());
+
+    let map = create_test_map(MapType::Array, 4);
+    assert!(map.mmap().is_err());
+}
+
+/// Create a standalone keyless map with 4 byte values for testing.
+fn create_keyless_test_map(map_type: MapType) -> Map {
+    let opts = libbpf_sys::bpf_map_create_opts {
+        sz: std::mem::size_of::<libbpf_sys::bpf_map_create_opts>() as libbpf_sys::size_t,
+        ..Default::default()
+    };
+
+    Map::create(map_type, Option::<&str>::None, 0, 4, 4, &opts).expect("failed to create map")
 }
 
 #[test]
-fn test_object_open_program_insns() {
+fn test_object_map_queue() {
     bump_rlimit_mlock();
 
-    let open_obj = open_test_object("usdt.bpf.o");
-    let prog = open_obj
-        .prog("handle__usdt")
-        .expect("Failed to find program");
+    let mut map = create_keyless_test_map(MapType::Queue);
+    assert!(map.contains(&(1_u32).to_ne_bytes()).is_err());
+    assert!(map.push(&[1], MapFlags::ANY).is_err());
+    assert_eq!(map.pop().expect("failed to pop"), None);
+    assert_eq!(map.peek().expect("failed to peek"), None);
 
-    let insns = prog.insns();
-    assert!(!insns.is_empty());
+    for i in 1..=4_u32 {
+        map.push(&i.to_ne_bytes(), MapFlags::ANY)
+            .expect("failed to push");
+    }
+    // The queue is full.
+    assert!(map.push(&(5_u32).to_ne_bytes(), MapFlags::ANY).is_err());
+    // ... unless we ask for the oldest element to be replaced.
+    map.push(&(5_u32).to_ne_bytes(), MapFlags::EXIST)
+        .expect("failed to push");
+
+    assert_eq!(map.peek().unwrap(), Some((2_u32).to_ne_bytes().to_vec()));
+    for i in 2..=5_u32 {
+        assert_eq!(map.pop().unwrap(), Some(i.to_ne_bytes().to_vec()));
+    }
+    assert_eq!(map.pop().unwrap(), None);
 }
 
 #[test]
-fn test_object_program_insns() {
+fn test_object_map_stack() {
     bump_rlimit_mlock();
 
-    let obj = get_test_object("usdt.bpf.o");
-    let prog = obj.prog("handle__usdt").expect("Failed to find program");
+    let mut map = create_keyless_test_map(MapType::Stack);
+    for i in 1..=3_u32 {
+        map.push(&i.to_ne_bytes(), MapFlags::ANY)
+            .expect("failed to push");
+    }
 
-    let insns = prog.insns();
-    assert!(!insns.is_empty());
+    assert_eq!(map.peek().unwrap(), Some((3_u32).to_ne_bytes().to_vec()));
+    for i in (1..=3_u32).rev() {
+        assert_eq!(map.pop().unwrap(), Some(i.to_ne_bytes().to_vec()));
+    }
+    assert_eq!(map.pop().unwrap(), None);
 }
 
-/// Check that we can attach a BPF program to a kernel tracepoint.
 #[test]
-fn test_object_tracepoint() {
+fn test_object_map_bloom_filter() {
     bump_rlimit_mlock();
 
-    let mut obj = get_test_object("tracepoint.bpf.o");
-    let prog = obj
-        .prog_mut("handle__tracepoint")
-        .expect("Failed to find program");
+    let mut map = create_keyless_test_map(MapType::BloomFilter);
+    assert!(map.pop().is_err());
+    assert!(map.peek().is_err());
+
+    map.push(&(42_u32).to_ne_bytes(), MapFlags::ANY)
+        .expect("failed to push");
+    assert!(map
+        .contains(&(42_u32).to_ne_bytes())
+        .expect("failed to check bloom filter"));
+    assert!(map.contains(&[42]).is_err());
+
+    // Non-keyless maps cannot be used with the keyless API.
+    let mut map = create_test_map(MapType::Hash, 4);
+    assert!(map.push(&[0; 8], MapFlags::ANY).is_err());
+    assert!(map.pop().is_err());
+}
 
-    let _link = prog
-        .attach_tracepoint("syscalls", "sys_enter_getpid")
-        .expect("Failed to attach prog");
+#[test]
+fn test_object_map_lpm_trie() {
+    bump_rlimit_mlock();
 
-    let map = obj.map("ringbuf").expect("Failed to get ringbuf map");
-    let action = || {
-        let _pid = unsafe { libc::getpid() };
+    let opts = libbpf_sys::bpf_map_create_opts {
+        sz: std::mem::size_of::<libbpf_sys::bpf_map_create_opts>() as libbpf_sys::size_t,
+        map_flags: libbpf_sys::BPF_F_NO_PREALLOC,
+        ..Default::default()
     };
-    let result = with_ringbuffer(map, action);
+    let mut map = Map::create(MapType::LpmTrie, Option::<&str>::None, 8, 4, 16, &opts)
+        .expect("failed to create map");
 
-    assert_eq!(result, 1);
+    assert!(LpmTrie::<Ipv6LpmKey>::new(&mut map).is_err());
+    let mut trie = LpmTrie::<Ipv4LpmKey>::new(&mut map).expect("failed to create LPM trie");
+
+    let wide = "10.0.0.0/8".parse::<Ipv4LpmKey>().unwrap();
+    let narrow = Ipv4LpmKey::new(Ipv4Addr::new(10, 1, 0, 0), 16).unwrap();
+    trie.insert_prefix(&wide, &(1_u32).to_ne_bytes(), MapFlags::ANY)
+        .expect("failed to insert prefix");
+    trie.insert_prefix(&narrow, &(2_u32).to_ne_bytes(), MapFlags::ANY)
+        .expect("failed to insert prefix");
+
+    let lookup = |trie: &LpmTrie<Ipv4LpmKey>, addr: [u8; 4]| {
+        trie.lookup_longest_match(Ipv4Addr::from(addr))
+            .expect("failed to lookup")
+            .map(|val| u32::from_ne_bytes(val.try_into().unwrap()))
+    };
+    assert_eq!(lookup(&trie, [10, 1, 2, 3]), Some(2));
+    assert_eq!(lookup(&trie, [10, 2, 2, 3]), Some(1));
+    assert_eq!(lookup(&trie, [11, 1, 2, 3]), None);
+
+    let prefixes = trie.prefixes().collect::<HashSet<_>>();
+    assert_eq!(prefixes, HashSet::from([wide, narrow]));
+
+    trie.remove_prefix(&narrow)
+        .expect("failed to remove prefix");
+    assert_eq!(lookup(&trie, [10, 1, 2, 3]), Some(1));
 }
 
-/// Check that we can attach a BPF program to a kernel tracepoint, providing
-/// additional options.
 #[test]
-fn test_object_tracepoint_with_opts() {
+fn test_object_map_in_map() {
     bump_rlimit_mlock();
 
-    let cookie_val = 42u16;
-    let mut obj = get_test_object("tracepoint.bpf.o");
-    let prog = obj
+    let mut inner1 = create_test_map(MapType::Hash, 4);
+    let inner2 = create_test_map(MapType::Hash, 4);
+
+    let opts = libbpf_sys::bpf_map_create_opts {
+        sz: std::mem::size_of::<libbpf_sys::bpf_map_create_opts>() as libbpf_sys::size_t,
+        inner_map_fd: inner1.fd() as u32,
+        ..Default::default()
+    };
+    let mut outer = Map::create(MapType::ArrayOfMaps, Option::<&str>::None, 4, 4, 2, &opts)
+        .expect("failed to create map");
+
+    // Maps created from user space don't carry an inner map definition.
+    assert!(outer.create_inner_map(Option::<&str>::None).is_err());
+    assert!(inner1
+        .update_inner_map(&[0; 4], &inner2, MapFlags::ANY)
+        .is_err());
+
+    let key = (1_u32).to_ne_bytes();
+    assert!(outer
+        .lookup_inner_map(&key)
+        .expect("failed to lookup inner map")
+        .is_none());
+
+    outer
+        .update_inner_map(&key, &inner1, MapFlags::ANY)
+        .expect("failed to insert inner map");
+    inner1
+        .update(&[1, 2, 3, 4], &[1, 2, 3, 4, 5, 6, 7, 8], MapFlags::ANY)
+        .expect("failed to write");
+
+    let mut inner = outer
+        .lookup_inner_map(&key)
+        .expect("failed to lookup inner map")
+        .expect("failed to find inner map");
+    assert_eq!(inner.map_type(), MapType::Hash);
+    assert_eq!(inner.key_size(), 4);
+    assert_eq!(inner.value_size(), 8);
+    assert_eq!(
+        inner
+            .lookup(&[1, 2, 3, 4], MapFlags::ANY)
+            .expect("failed to lookup")
+            .expect("failed to find value for key"),
+        vec![1, 2, 3, 4, 5, 6, 7, 8]
+    );
+
+    // Writes through the new handle are visible through the original one.
+    inner.delete(&[1, 2, 3, 4]).expect("failed to delete key");
+    assert_eq!(inner1.keys().count(), 0);
+
+    outer
+        .update_inner_map(&key, &inner2, MapFlags::EXIST)
+        .expect("failed to replace inner map");
+    outer.delete(&key).expect("failed to delete inner map");
+    assert!(outer
+        .lookup_inner_map(&key)
+        .expect("failed to lookup inner map")
+        .is_none());
+}
+
+#[test]
+fn test_object_map_from_pinned_path() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let map = obj.map_mut("start").expect("failed to find map");
+
+    let path = "/sys/fs/bpf/mymap_test_object_map_from_pinned_path";
+    let err = Map::from_pinned_path(path).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+
+    map.pin(path).expect("failed to pin map");
+    defer! {
+        let _ = fs::remove_file(path);
+    }
+
+    let err = Program::from_pinned_path(path).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let mut pinned = Map::from_pinned_path(path).expect("failed to open pinned map");
+    assert_eq!(pinned.name(), map.name());
+    assert_eq!(pinned.map_type(), map.map_type());
+    assert_eq!(pinned.key_size(), map.key_size());
+    assert_eq!(pinned.value_size(), map.value_size());
+
+    let key = (1_u32).to_ne_bytes();
+    let value = (2_u64).to_ne_bytes();
+    pinned
+        .update(&key, &value, MapFlags::ANY)
+        .expect("failed to write");
+    assert_eq!(
+        map.lookup(&key, MapFlags::ANY)
+            .expect("failed to lookup")
+            .expect("failed to find value for key"),
+        value
+    );
+
+    pinned.unpin(path).expect("failed to unpin map");
+    assert!(!Path::new(path).exists());
+}
+
+#[test]
+fn test_object_map_from_id() {
+    bump_rlimit_mlock();
+
+    let opts = libbpf_sys::bpf_map_create_opts {
+        sz: std::mem::size_of::<libbpf_sys::bpf_map_create_opts>() as libbpf_sys::size_t,
+        ..Default::default()
+    };
+    let name = "mymap_from_id";
+    let map = Map::create(MapType::Hash, Some(name), 4, 8, 8, &opts).expect("failed to create map");
+
+    let id = query::MapInfoIter::default()
+        .find(|info| info.name == name)
+        .expect("failed to find map info")
+        .id;
+
+    let opened = Map::from_id(id).expect("failed to open map by id");
+    assert_eq!(opened.name(), name);
+    assert_eq!(opened.map_type(), MapType::Hash);
+    assert_eq!(opened.key_size(), 4);
+    assert_eq!(opened.value_size(), 8);
+    assert_ne!(opened.fd(), map.fd());
+}
+
+#[test]
+fn test_object_map_error_context() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let map = obj.map_mut("start").expect("failed to find map");
+
+    let key = (1_u32).to_ne_bytes();
+    let value = (2_u64).to_ne_bytes();
+    map.update(&key, &value, MapFlags::NO_EXIST)
+        .expect("failed to write");
+    let err = map.update(&key, &value, MapFlags::NO_EXIST).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+    assert!(err.to_string().contains("update map `start`"));
+
+    let err = io::Error::from(err);
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    assert_eq!(Error::from(err).kind(), ErrorKind::AlreadyExists);
+}
+
+/// Retrieve the kernel assigned ID of the program referred to by `fd`.
+pub fn prog_id(fd: i32) -> u32 {
+    let mut info: libbpf_sys::bpf_prog_info = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libbpf_sys::bpf_prog_info>() as u32;
+    let ret =
+        unsafe { libbpf_sys::bpf_obj_get_info_by_fd(fd, &mut info as *mut _ as *mut _, &mut len) };
+    assert_eq!(ret, 0);
+    info.id
+}
+
+#[test]
+fn test_object_program_from_pinned_path() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+
+    let path = "/sys/fs/bpf/myprog_test_object_program_from_pinned_path";
+    assert!(Program::from_pinned_path(path).is_err());
+
+    prog.pin(path).expect("failed to pin prog");
+    defer! {
+        let _ = fs::remove_file(path);
+    }
+
+    let err = Map::from_pinned_path(path).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let mut pinned = Program::from_pinned_path(path).expect("failed to open pinned prog");
+    // The kernel truncates names to 15 characters.
+    assert!(prog.name().starts_with(pinned.name()));
+    assert!(matches!(pinned.prog_type(), ProgramType::Tracing));
+    assert_eq!(pinned.section(), "");
+    assert_eq!(prog_id(pinned.fd()), prog_id(prog.fd()));
+
+    // Programs not belonging to an object can't be attached via libbpf.
+    assert!(pinned.attach().is_err());
+
+    pinned.unpin(path).expect("failed to unpin prog");
+    assert!(!Path::new(path).exists());
+}
+
+#[test]
+fn test_object_program_from_id() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog("handle__sched_wakeup")
+        .expect("failed to find program");
+
+    let id = prog_id(prog.fd());
+    let opened = Program::from_id(id).expect("failed to open prog by id");
+    assert!(prog.name().starts_with(opened.name()));
+    assert!(matches!(opened.prog_type(), ProgramType::Tracing));
+    assert_ne!(opened.fd(), prog.fd());
+    assert_eq!(prog_id(opened.fd()), id);
+}
+
+#[test]
+fn test_object_usdt() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("usdt.bpf.o");
+    let prog = obj
+        .prog_mut("handle__usdt")
+        .expect("Failed to find program");
+
+    let path = std::env::current_exe().expect("Failed to find executable name");
+    let _link = prog
+        .attach_usdt(
+            unsafe { libc::getpid() },
+            &path,
+            "test_provider",
+            "test_function",
+        )
+        .expect("Failed to attach prog");
+
+    let map = obj.map("ringbuf").expect("Failed to get ringbuf map");
+    let action = || {
+        // Define a USDT probe point and exercise it as we are attaching to self.
+        probe!(test_provider, test_function, 1);
+    };
+    let result = with_ringbuffer(map, action);
+
+    assert_eq!(result, 1);
+}
+
+#[test]
+fn test_object_usdt_cookie() {
+    bump_rlimit_mlock();
+
+    let cookie_val = 1337u16;
+    let mut obj = get_test_object("usdt.bpf.o");
+    let prog = obj
+        .prog_mut("handle__usdt_with_cookie")
+        .expect("Failed to find program");
+
+    let path = std::env::current_exe().expect("Failed to find executable name");
+    let _link = prog
+        .attach_usdt_with_opts(
+            unsafe { libc::getpid() },
+            &path,
+            "test_provider",
+            "test_function",
+            UsdtOpts {
+                cookie: cookie_val.into(),
+                ..UsdtOpts::default()
+            },
+        )
+        .expect("Failed to attach prog");
+
+    let map = obj.map("ringbuf").expect("Failed to get ringbuf map");
+    let action = || {
+        // Define a USDT probe point and exercise it as we are attaching to self.
+        probe!(test_provider, test_function, 1);
+    };
+    let result = with_ringbuffer(map, action);
+
+    assert_eq!(result, cookie_val.into());
+}
+
+#[test]
+fn test_object_map_probes() {
+    bump_rlimit_mlock();
+
+    let supported = MapType::Array
+        .is_supported()
+        .expect("Failed to query if Array map is supported");
+    assert!(supported);
+    let supported_res = MapType::Unknown.is_supported();
+    assert!(supported_res.is_err());
+}
+
+#[test]
+fn test_object_program_probes() {
+    bump_rlimit_mlock();
+
+    let supported = ProgramType::SocketFilter
+        .is_supported()
+        .expect("Failed to query if SocketFilter program is supported");
+    assert!(supported);
+    let supported_res = ProgramType::Unknown.is_supported();
+    assert!(supported_res.is_err());
+}
+
+#[test]
+fn test_object_program_helper_probes() {
+    bump_rlimit_mlock();
+
+    let supported = ProgramType::SocketFilter
+        .is_helper_supported(libbpf_sys::BPF_FUNC_map_lookup_elem)
+        .expect("Failed to query if helper supported");
+    assert!(supported);
+    // redirect should not be supported from socket filter, as it is only used in TC/XDP.
+    let supported = ProgramType::SocketFilter
+        .is_helper_supported(libbpf_sys::BPF_FUNC_redirect)
+        .expect("Failed to query if helper supported");
+    assert!(!supported);
+    let supported_res = MapType::Unknown.is_supported();
+    assert!(supported_res.is_err());
+}
+
+#[test]
+fn test_object_open_program_insns() {
+    bump_rlimit_mlock();
+
+    let open_obj = open_test_object("usdt.bpf.o");
+    let prog = open_obj
+        .prog("handle__usdt")
+        .expect("Failed to find program");
+
+    let insns = prog.insns();
+    assert!(!insns.is_empty());
+}
+
+#[test]
+fn test_object_program_insns() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("usdt.bpf.o");
+    let prog = obj.prog("handle__usdt").expect("Failed to find program");
+
+    let insns = prog.insns();
+    assert!(!insns.is_empty());
+}
+
+/// Check that we can run a BPF program on provided input.
+#[test]
+fn test_object_program_test_run() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("tc-unit.bpf.o");
+    let prog = obj.prog("handle_tc").expect("failed to find program");
+
+    // An Ethernet frame with an IPv4 header, padded to the minimum length.
+    let mut packet = vec![0u8; 64];
+    packet[12..14].copy_from_slice(&0x0800u16.to_be_bytes());
+    packet[14] = 0x45;
+
+    let opts = TestRunOpts {
+        data_in: &packet,
+        data_out_size: packet.len(),
+        repeat: 10,
+        ..Default::default()
+    };
+    let output = prog.test_run(opts).expect("failed to test run program");
+    // The program returns TC_ACT_UNSPEC, i.e., -1.
+    assert_eq!(output.return_value as i32, -1);
+    assert_eq!(output.data_out, packet);
+    assert!(output.context_out.is_empty());
+
+    let opts = TestRunOpts {
+        data_in: &packet,
+        data_out_size: 1,
+        ..Default::default()
+    };
+    let err = prog.test_run(opts).unwrap_err();
+    assert_eq!(err.errno(), Some(errno::Errno::ENOSPC as i32));
+
+    let opts = TestRunOpts {
+        data_in: &packet,
+        repeat: u32::MAX,
+        ..Default::default()
+    };
+    let err = prog.test_run(opts).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+/// Create a cgroup for testing, below the cgroup v2 hierarchy's root.
+fn create_test_cgroup(name: &str) -> PathBuf {
+    let mounts = fs::read_to_string("/proc/mounts").expect("failed to read mounts");
+    let root = mounts
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .find(|fields| fields.get(2) == Some(&"cgroup2"))
+        .map(|fields| PathBuf::from(fields[1]))
+        .expect("failed to find cgroup2 mount");
+
+    let path = root.join(format!("libbpf-rs-{}-{}", name, std::process::id()));
+    fs::create_dir(&path).expect("failed to create cgroup");
+    path
+}
+
+/// Load the programs of tracepoint.bpf.o as cgroup egress programs.
+fn get_cgroup_test_object() -> Object {
+    let mut open_obj = open_test_object("tracepoint.bpf.o");
+    for prog in open_obj.progs_iter_mut() {
+        prog.set_prog_type(ProgramType::CgroupSkb);
+        prog.set_attach_type(ProgramAttachType::CgroupInetEgress);
+    }
+    open_obj.load().expect("failed to load object")
+}
+
+/// Check that we can attach BPF programs to a cgroup, using the legacy
+/// `BPF_PROG_ATTACH` command.
+#[test]
+fn test_object_cgroup_legacy() {
+    bump_rlimit_mlock();
+
+    let path = create_test_cgroup("legacy");
+    defer! {
+        let _ = fs::remove_dir(&path);
+    }
+    let cgroup = fs::File::open(&path).expect("failed to open cgroup");
+    let cgroup_fd = cgroup.as_raw_fd();
+
+    let obj = get_cgroup_test_object();
+    let prog1 = obj.prog("handle__tracepoint").unwrap();
+    let prog2 = obj.prog("handle__tracepoint_with_cookie").unwrap();
+
+    let egress = ProgramAttachType::CgroupInetEgress;
+
+    let multi = CgroupAttachOpts {
+        allow_multi: true,
+        ..Default::default()
+    };
+    prog1
+        .attach_cgroup_legacy(cgroup_fd, egress.clone(), multi.clone())
+        .expect("failed to attach program");
+
+    let replace = CgroupAttachOpts {
+        replace_prog_fd: Some(prog1.fd()),
+        ..Default::default()
+    };
+    let err = prog2
+        .attach_cgroup_legacy(cgroup_fd, egress.clone(), replace)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    // Programs opened by id have no known attach type, but can be attached
+    // nonetheless.
+    let prog2 = Program::from_id(prog_id(prog2.fd())).expect("failed to open program");
+    let replace = CgroupAttachOpts {
+        allow_multi: true,
+        replace_prog_fd: Some(prog1.fd()),
+        ..Default::default()
+    };
+    prog2
+        .attach_cgroup_legacy(cgroup_fd, egress.clone(), replace)
+        .expect("failed to replace program");
+
+    let err = prog1
+        .detach_cgroup_legacy(cgroup_fd, egress.clone())
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    prog2
+        .detach_cgroup_legacy(cgroup_fd, egress)
+        .expect("failed to detach program");
+}
+
+/// Check that we can attach a BPF program to a cgroup given its path.
+#[test]
+fn test_object_cgroup_path() {
+    bump_rlimit_mlock();
+
+    let path = create_test_cgroup("path");
+    defer! {
+        let _ = fs::remove_dir(&path);
+    }
+
+    let mut obj = get_cgroup_test_object();
+    let prog = obj.prog_mut("handle__tracepoint").unwrap();
+    let link = prog
+        .attach_cgroup_path(&path)
+        .expect("failed to attach program");
+    drop(link);
+
+    let err = prog
+        .attach_cgroup_path(path.join("does-not-exist"))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
+
+/// Check that we can query the programs attached to a cgroup.
+#[test]
+fn test_object_cgroup_prog_query() {
+    bump_rlimit_mlock();
+
+    let path = create_test_cgroup("query");
+    defer! {
+        let _ = fs::remove_dir(&path);
+    }
+    let cgroup = fs::File::open(&path).expect("failed to open cgroup");
+    let cgroup_fd = cgroup.as_raw_fd();
+
+    let info = query::prog_query(cgroup_fd, ProgramAttachType::CgroupInetEgress, false)
+        .expect("failed to query programs");
+    assert!(info.progs.is_empty());
+
+    let obj = get_cgroup_test_object();
+    let prog1 = obj.prog("handle__tracepoint").unwrap();
+    let prog2 = obj.prog("handle__tracepoint_with_cookie").unwrap();
+    let opts = CgroupAttachOpts {
+        allow_multi: true,
+        ..Default::default()
+    };
+    for prog in [prog1, prog2] {
+        prog.attach_cgroup_legacy(cgroup_fd, ProgramAttachType::CgroupInetEgress, opts.clone())
+            .expect("failed to attach program");
+    }
+    defer! {
+        let _ = prog1.detach_cgroup_legacy(cgroup_fd, ProgramAttachType::CgroupInetEgress);
+        let _ = prog2.detach_cgroup_legacy(cgroup_fd, ProgramAttachType::CgroupInetEgress);
+    }
+
+    let info = query::prog_query(cgroup_fd, ProgramAttachType::CgroupInetEgress, false)
+        .expect("failed to query programs");
+    let ids = info
+        .progs
+        .iter()
+        .map(|prog| prog.prog_id)
+        .collect::<Vec<_>>();
+    assert_eq!(ids, vec![prog_id(prog1.fd()), prog_id(prog2.fd())]);
+    assert_eq!(info.attach_flags, libbpf_sys::BPF_F_ALLOW_MULTI);
+    for prog in &info.progs {
+        assert_eq!(prog.attach_flags, libbpf_sys::BPF_F_ALLOW_MULTI);
+        assert_eq!(prog.link_id, None);
+        let prog_info = prog.prog_info().expect("failed to get program info");
+        assert_eq!(prog_info.id, prog.prog_id);
+        assert!(matches!(prog_info.ty, ProgramType::CgroupSkb));
+    }
+
+    let info = query::prog_query(cgroup_fd, ProgramAttachType::CgroupInetEgress, true)
+        .expect("failed to query effective programs");
+    assert_eq!(info.progs.len(), 2);
+}
+
+/// Check that a program attached to a cgroup through a link is reported by
+/// `query::prog_query()`, and no longer once the link is gone.
+#[test]
+fn test_object_cgroup_prog_query_link() {
+    bump_rlimit_mlock();
+
+    let path = create_test_cgroup("query_link");
+    defer! {
+        let _ = fs::remove_dir(&path);
+    }
+    let cgroup = fs::File::open(&path).expect("failed to open cgroup");
+    let cgroup_fd = cgroup.as_raw_fd();
+
+    let mut obj = get_cgroup_test_object();
+    let prog = obj.prog_mut("handle__tracepoint").unwrap();
+    let link = prog
+        .attach_cgroup(cgroup_fd)
+        .expect("failed to attach program");
+
+    let info = query::prog_query(cgroup_fd, ProgramAttachType::CgroupInetEgress, false)
+        .expect("failed to query programs");
+    assert_eq!(info.progs.len(), 1);
+    let attached = &info.progs[0];
+    assert_eq!(attached.prog_id, prog_id(prog.fd()));
+    let prog_info = attached.prog_info().expect("failed to get program info");
+    assert_eq!(prog_info.id, attached.prog_id);
+    assert_eq!(prog_info.name, "handle__tracepo");
+    assert!(matches!(prog_info.ty, ProgramType::CgroupSkb));
+
+    drop(link);
+    let info = query::prog_query(cgroup_fd, ProgramAttachType::CgroupInetEgress, false)
+        .expect("failed to query programs");
+    assert!(info.progs.is_empty());
+}
+
+/// Check that we can attach a BPF program to a kernel tracepoint.
+#[test]
+fn test_object_tracepoint() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("tracepoint.bpf.o");
+    let prog = obj
+        .prog_mut("handle__tracepoint")
+        .expect("Failed to find program");
+
+    let _link = prog
+        .attach_tracepoint("syscalls", "sys_enter_getpid")
+        .expect("Failed to attach prog");
+
+    let map = obj.map("ringbuf").expect("Failed to get ringbuf map");
+    let action = || {
+        let _pid = unsafe { libc::getpid() };
+    };
+    let result = with_ringbuffer(map, action);
+
+    assert_eq!(result, 1);
+}
+
+/// Check that we can attach a BPF program to perf events opened for a
+/// kernel tracepoint.
+#[test]
+fn test_object_perf_event_tracepoint() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("tracepoint.bpf.o");
+    let prog = obj
+        .prog_mut("handle__tracepoint")
+        .expect("Failed to find program");
+
+    let links = PerfEventBuilder::new(PerfEvent::Tracepoint {
+        category: "syscalls".to_string(),
+        name: "sys_enter_getpid".to_string(),
+    })
+    .pid(0)
+    .attach(prog)
+    .expect("Failed to attach prog");
+    assert_eq!(links.len(), 1);
+
+    let map = obj.map("ringbuf").expect("Failed to get ringbuf map");
+    let action = || {
+        let _pid = unsafe { libc::getpid() };
+    };
+    let result = with_ringbuffer(map, action);
+
+    assert_eq!(result, 1);
+
+    let err = PerfEventBuilder::new(PerfEvent::Tracepoint {
+        category: "syscalls".to_string(),
+        name: "sys_enter_does_not_exist".to_string(),
+    })
+    .attach(obj.prog_mut("handle__tracepoint").unwrap())
+    .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
+
+/// Find the name of the kernel function handling the getpid() syscall, which
+/// differs between architectures, e.g., `__x64_sys_getpid`.
+fn getpid_syscall_handler() -> String {
+    let kallsyms = fs::read_to_string("/proc/kallsyms").expect("failed to read kallsyms");
+    kallsyms
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(2))
+        .find(|sym| sym.starts_with("__") && sym.ends_with("_sys_getpid"))
+        .expect("failed to find getpid syscall handler")
+        .to_string()
+}
+
+/// Check that we can attach a BPF program to a kprobe, providing additional
+/// options.
+#[test]
+fn test_object_kprobe_with_opts() {
+    bump_rlimit_mlock();
+
+    let mut open_obj = open_test_object("tracepoint.bpf.o");
+    let prog = open_obj
+        .prog_mut("handle__tracepoint_with_cookie")
+        .expect("Failed to find program");
+    prog.set_prog_type(ProgramType::Kprobe);
+    let mut obj = open_obj.load().expect("failed to load object");
+    let prog = obj
+        .prog_mut("handle__tracepoint_with_cookie")
+        .expect("Failed to find program");
+
+    let opts = KprobeOpts {
+        maxactive: 16,
+        ..Default::default()
+    };
+    let err = prog
+        .attach_kprobe_with_opts(getpid_syscall_handler(), opts)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+
+    let opts = KprobeOpts {
+        cookie: 42,
+        attach_mode: ProbeAttachMode::Perf,
+        ..Default::default()
+    };
+    let _link = prog
+        .attach_kprobe_with_opts(getpid_syscall_handler(), opts)
+        .expect("Failed to attach prog");
+
+    let map = obj.map("ringbuf").expect("Failed to get ringbuf map");
+    let action = || {
+        let _pid = unsafe { libc::getpid() };
+    };
+    let result = with_ringbuffer(map, action);
+
+    assert_eq!(result, 42);
+}
+
+/// Open tracepoint.bpf.o with `handle__tracepoint` turned into a program of
+/// type `ty`, with its sibling using attach cookies disabled.
+fn get_socket_test_object(ty: ProgramType, attach_type: Option<ProgramAttachType>) -> Object {
+    let mut open_obj = open_test_object("tracepoint.bpf.o");
+    let prog = open_obj.prog_mut("handle__tracepoint").unwrap();
+    prog.set_prog_type(ty);
+    if let Some(attach_type) = attach_type {
+        prog.set_attach_type(attach_type);
+    }
+    let prog = open_obj.prog_mut("handle__tracepoint_with_cookie").unwrap();
+    prog.set_autoload(false).unwrap();
+    open_obj.load().expect("failed to load object")
+}
+
+/// Check that we can attach a socket filter program to a socket.
+#[test]
+fn test_object_socket_filter() {
+    bump_rlimit_mlock();
+
+    let obj = get_socket_test_object(ProgramType::SocketFilter, None);
+    let prog = obj.prog("handle__tracepoint").unwrap();
+    let map = obj.map("ringbuf").unwrap();
+
+    let receiver = UdpSocket::bind("127.0.0.1:0").expect("failed to bind socket");
+    let sender = UdpSocket::bind("127.0.0.1:0").expect("failed to bind socket");
+    let addr = receiver.local_addr().unwrap();
+
+    prog.attach_socket(&receiver)
+        .expect("failed to attach program");
+    let result = with_ringbuffer(map, || {
+        sender.send_to(&[1, 2, 3], addr).unwrap();
+    });
+    assert_eq!(result, 1);
+
+    detach_socket(&receiver).expect("failed to detach program");
+    let result = with_ringbuffer(map, || {
+        sender.send_to(&[1, 2, 3], addr).unwrap();
+    });
+    assert_eq!(result, 0);
+
+    let err = detach_socket(&receiver).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
+
+/// Check that we can attach a program to a `SO_REUSEPORT` group.
+#[test]
+fn test_object_reuseport() {
+    bump_rlimit_mlock();
+
+    let obj = get_socket_test_object(
+        ProgramType::SkReuseport,
+        Some(ProgramAttachType::SkReuseportSelect),
+    );
+    let prog = obj.prog("handle__tracepoint").unwrap();
+
+    let fd = socket(
+        AddressFamily::Inet,
+        SockType::Datagram,
+        SockFlag::SOCK_CLOEXEC,
+        None,
+    )
+    .expect("failed to create socket");
+    let socket = unsafe { UdpSocket::from_raw_fd(fd) };
+    let () = setsockopt(fd, sockopt::ReusePort, &true).expect("failed to set SO_REUSEPORT");
+    let () = bind(fd, &SockaddrIn::new(127, 0, 0, 1, 0)).expect("failed to bind socket");
+
+    prog.attach_reuseport(&socket)
+        .expect("failed to attach program");
+    detach_reuseport(&socket).expect("failed to detach program");
+
+    let err = detach_reuseport(&socket).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
+
+/// Check that we can attach a BTF enabled tracing program with a cookie.
+#[test]
+fn test_object_trace_with_opts() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+
+    let opts = TraceOpts {
+        cookie: 42,
+        ..Default::default()
+    };
+    let _link = prog
+        .attach_trace_with_opts(opts)
+        .expect("failed to attach program");
+}
+
+/// Check that attaching a program that is not an extension via freplace
+/// fails.
+#[test]
+fn test_object_freplace_invalid() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let target_fd = obj.prog("handle__sched_switch").unwrap().fd();
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+
+    let err = prog
+        .attach_freplace(target_fd, "trace_enqueue")
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+/// Check that we can attach a BPF program to multiple kprobes at once.
+#[test]
+fn test_object_kprobe_multi() {
+    bump_rlimit_mlock();
+
+    let getpid = getpid_syscall_handler();
+    let mut open_obj = open_test_object("tracepoint.bpf.o");
+    let prog = open_obj
+        .prog_mut("handle__tracepoint_with_cookie")
+        .expect("Failed to find program");
+    prog.set_prog_type(ProgramType::Kprobe);
+    prog.set_attach_type(ProgramAttachType::TraceKprobeMulti);
+    let mut obj = open_obj.load().expect("failed to load object");
+    let prog = obj
+        .prog_mut("handle__tracepoint_with_cookie")
+        .expect("Failed to find program");
+
+    let opts = KprobeMultiOpts {
+        pattern: Some(getpid.clone()),
+        symbols: vec![getpid.clone()],
+        ..Default::default()
+    };
+    let err = prog.attach_kprobe_multi_with_opts(opts).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let opts = KprobeMultiOpts {
+        symbols: vec![getpid],
+        cookies: vec![42],
+        ..Default::default()
+    };
+    let _link = prog
+        .attach_kprobe_multi_with_opts(opts)
+        .expect("Failed to attach prog");
+
+    let map = obj.map("ringbuf").expect("Failed to get ringbuf map");
+    let action = || {
+        let _pid = unsafe { libc::getpid() };
+    };
+    let result = with_ringbuffer(map, action);
+
+    assert_eq!(result, 42);
+}
+
+#[test]
+fn test_object_ringbuf_channel() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("tracepoint.bpf.o");
+    let prog = obj
+        .prog_mut("handle__tracepoint")
+        .expect("Failed to find program");
+
+    let _link = prog
+        .attach_tracepoint("syscalls", "sys_enter_getpid")
+        .expect("Failed to attach prog");
+
+    let map = obj.map("ringbuf").expect("Failed to get ringbuf map");
+    let mut builder = libbpf_rs::RingBufferBuilder::new();
+    let receiver = builder
+        .add_channel_typed::<i32>(map, 1, Backpressure::DropNewest)
+        .expect("Failed to add ringbuf");
+    let ringbuf = builder.build().expect("Failed to build");
+
+    let _pid = unsafe { libc::getpid() };
+    let _pid = unsafe { libc::getpid() };
+    ringbuf.consume().expect("Failed to consume ringbuf");
+
+    // Other processes may call getpid() as well, so there may be more events.
+    assert_eq!(receiver.try_recv(), Some(1));
+    assert!(receiver.try_recv().is_none());
+    assert!(receiver.dropped() >= 1);
+
+    drop(ringbuf);
+    assert!(receiver.recv().is_none());
+}
+
+#[test]
+fn test_object_ringbuf_channel_stop() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("tracepoint.bpf.o");
+    let prog = obj
+        .prog_mut("handle__tracepoint")
+        .expect("Failed to find program");
+
+    let _link = prog
+        .attach_tracepoint("syscalls", "sys_enter_getpid")
+        .expect("Failed to attach prog");
+
+    let map = obj.map("ringbuf").expect("Failed to get ringbuf map");
+    let mut builder = libbpf_rs::RingBufferBuilder::new();
+    let receiver = builder
+        .add_channel_typed::<i32>(map, 16, Backpressure::DropNewest)
+        .expect("Failed to add ringbuf");
+    let ringbuf = builder.build().expect("Failed to build");
+
+    let _pid = unsafe { libc::getpid() };
+    ringbuf.consume().expect("Failed to consume ringbuf");
+    assert_eq!(receiver.try_recv(), Some(1));
+
+    receiver.stop();
+    let _pid = unsafe { libc::getpid() };
+    let err = ringbuf.consume().unwrap_err();
+    assert_eq!(err.errno(), Some(libc::EPIPE));
+    assert!(receiver.dropped() >= 1);
+}
+
+#[test]
+fn test_object_ringbuf_consume_n() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("tracepoint.bpf.o");
+    let prog = obj
+        .prog_mut("handle__tracepoint")
+        .expect("Failed to find program");
+
+    let _link = prog
+        .attach_tracepoint("syscalls", "sys_enter_getpid")
+        .expect("Failed to attach prog");
+
+    let map = obj.map("ringbuf").expect("Failed to get ringbuf map");
+    let mut builder = libbpf_rs::RingBufferBuilder::new();
+    let receiver = builder
+        .add_channel_typed::<i32>(map, 16, Backpressure::DropNewest)
+        .expect("Failed to add ringbuf");
+    let ringbuf = builder.build().expect("Failed to build");
+
+    assert_eq!(ringbuf.ring_count(), 1);
+    assert_eq!(ringbuf.consume_n(0).expect("Failed to consume ringbuf"), 0);
+
+    let _pid = unsafe { libc::getpid() };
+    let _pid = unsafe { libc::getpid() };
+
+    // Other processes may call getpid() as well, so there may be more events.
+    assert_eq!(ringbuf.consume_n(1).expect("Failed to consume ringbuf"), 1);
+    assert_eq!(receiver.try_recv(), Some(1));
+    assert!(receiver.try_recv().is_none());
+
+    // Stay within the capacity of the channel, so that no event gets dropped.
+    let n = ringbuf.consume_n(8).expect("Failed to consume ringbuf");
+    assert!(n >= 1);
+    assert_eq!(receiver.iter().take(n).count(), n);
+}
+
+/// Check that a callback returning the same error that stops consumption
+/// once the budget of `RingBuffer::consume_n()` is exhausted is reported.
+#[test]
+fn test_object_ringbuf_consume_n_callback_error() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("tracepoint.bpf.o");
+    let prog = obj
+        .prog_mut("handle__tracepoint")
+        .expect("Failed to find program");
+
+    let _link = prog
+        .attach_tracepoint("syscalls", "sys_enter_getpid")
+        .expect("Failed to attach prog");
+
+    let map = obj.map("ringbuf").expect("Failed to get ringbuf map");
+    let mut builder = libbpf_rs::RingBufferBuilder::new();
+    builder
+        .add(map, |_| -libc::ECANCELED)
+        .expect("Failed to add ringbuf");
+    let ringbuf = builder.build().expect("Failed to build");
+
+    let _pid = unsafe { libc::getpid() };
+    let err = ringbuf.consume_n(8).unwrap_err();
+    assert_eq!(err.errno(), Some(libc::ECANCELED));
+}
+
+#[test]
+fn test_object_ringbuf_add_after_build() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("tracepoint.bpf.o");
+    let prog = obj
+        .prog_mut("handle__tracepoint")
+        .expect("Failed to find program");
+
+    let _link = prog
+        .attach_tracepoint("syscalls", "sys_enter_getpid")
+        .expect("Failed to attach prog");
+
+    let opts = libbpf_sys::bpf_map_create_opts {
+        sz: std::mem::size_of::<libbpf_sys::bpf_map_create_opts>() as libbpf_sys::size_t,
+        ..Default::default()
+    };
+    let other = Map::create(MapType::RingBuf, Option::<&str>::None, 0, 0, 4096, &opts)
+        .expect("failed to create map");
+
+    let map = obj.map("ringbuf").expect("Failed to get ringbuf map");
+    let mut value = 0i32;
+    let callback = |data: &[u8]| {
+        plain::copy_from_bytes(&mut value, data).expect("Wrong size");
+        0
+    };
+    let mut builder = libbpf_rs::RingBufferBuilder::new();
+    builder.add(&other, |_| 0).expect("Failed to add ringbuf");
+    let mut ringbuf = builder.build().expect("Failed to build");
+    ringbuf.add(map, callback).expect("Failed to add ringbuf");
+    assert_eq!(ringbuf.ring_count(), 2);
+
+    let _pid = unsafe { libc::getpid() };
+    ringbuf.consume().expect("Failed to consume ringbuf");
+    drop(ringbuf);
+
+    assert_eq!(value, 1);
+}
+
+/// Check that we can receive raw perf event records from a selected CPU.
+#[test]
+fn test_object_perf_buffer_raw() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let _links = obj
+        .progs_iter_mut()
+        .map(|prog| prog.attach().expect("failed to attach prog"))
+        .collect::<Vec<_>>();
+
+    let cpu = unsafe { libc::sched_getcpu() };
+    assert!(cpu >= 0);
+
+    let map = obj.map("events").expect("failed to find map");
+    let mut samples = 0;
+    let mut other_cpu = 0;
+    let perfbuf = PerfBufferBuilder::new(map)
+        .cpus(&[cpu])
+        .wakeup_events(1)
+        .event_cb(|event_cpu, header, data| {
+            if event_cpu != cpu {
+                other_cpu += 1;
+            } else if header.type_ == libbpf_sys::PERF_RECORD_SAMPLE && !data.is_empty() {
+                samples += 1;
+            }
+        })
+        .build()
+        .expect("failed to build perf buffer");
+    assert_eq!(perfbuf.buffer_cnt(), 1);
+
+    // Context switches on the selected CPU produce samples, so sleeping
+    // should do.
+    for _ in 0..100 {
+        std::thread::sleep(Duration::from_millis(1));
+        perfbuf
+            .poll(Duration::from_millis(10))
+            .expect("failed to poll perf buffer");
+    }
+    let _lost = perfbuf.total_lost_count();
+    drop(perfbuf);
+
+    assert!(samples > 0);
+    assert_eq!(other_cpu, 0);
+}
+
+/// Check that we can reserve, submit, and discard samples in a user ring
+/// buffer.
+#[test]
+fn test_user_ringbuf() {
+    bump_rlimit_mlock();
+
+    let opts = libbpf_sys::bpf_map_create_opts {
+        sz: std::mem::size_of::<libbpf_sys::bpf_map_create_opts>() as libbpf_sys::size_t,
+        ..Default::default()
+    };
+    let map = Map::create(
+        MapType::UserRingBuf,
+        Option::<&str>::None,
+        0,
+        0,
+        4096,
+        &opts,
+    )
+    .expect("failed to create map");
+    let rb = UserRingBuffer::new(&map).expect("failed to create user ring buffer");
+
+    let mut sample = rb.reserve(4).expect("failed to reserve sample");
+    assert_eq!(sample.len(), 4);
+    sample.copy_from_slice(&1337u32.to_ne_bytes());
+    sample.submit();
+
+    let sample = rb.reserve(8).expect("failed to reserve sample");
+    sample.discard();
+
+    let err = rb.reserve(8192).unwrap_err();
+    assert_eq!(err.errno(), Some(errno::Errno::E2BIG as i32));
+
+    let hash = Map::create(MapType::Hash, Option::<&str>::None, 4, 4, 8, &opts)
+        .expect("failed to create map");
+    let err = UserRingBuffer::new(&hash).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_object_tracepoint_async_ringbuf() {
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::sync::Arc;
+
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("tracepoint.bpf.o");
+    let prog = obj
+        .prog_mut("handle__tracepoint")
+        .expect("Failed to find program");
+
+    let _link = prog
+        .attach_tracepoint("syscalls", "sys_enter_getpid")
+        .expect("Failed to attach prog");
+
+    let map = obj.map("ringbuf").expect("Failed to get ringbuf map");
+    let value = Arc::new(AtomicI32::new(0));
+    let cb_value = value.clone();
+    let callback = move |data: &[u8]| {
+        let mut value = 0i32;
+        plain::copy_from_bytes(&mut value, data).expect("Wrong size");
+        cb_value.store(value, Ordering::Relaxed);
+        0
+    };
+    let mut builder = libbpf_rs::RingBufferBuilder::new();
+    builder.add(map, callback).expect("Failed to add ringbuf");
+    let ringbuf = builder.build().expect("Failed to build");
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build()
+        .expect("Failed to create runtime");
+    runtime.block_on(async {
+        let mut ringbuf = AsyncRingBuffer::new(ringbuf).expect("Failed to register ringbuf");
+        // `tokio::spawn()` requires the consume loop to be `Send`.
+        let task = tokio::spawn(async move {
+            while value.load(Ordering::Relaxed) == 0 {
+                ringbuf.consume().await.expect("Failed to consume ringbuf");
+            }
+            value.load(Ordering::Relaxed)
+        });
+        let _pid = unsafe { libc::getpid() };
+        assert_eq!(task.await.expect("consume task failed"), 1);
+    });
+}
+
+/// Check at compile time that the consume loop of an [`AsyncPerfBuffer`]
+/// can be spawned onto a tokio runtime.
+#[cfg(feature = "async")]
+#[allow(dead_code)]
+fn spawn_async_perfbuf(mut perfbuf: AsyncPerfBuffer<'static>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            perfbuf.consume().await.expect("Failed to consume perfbuf");
+        }
+    })
+}
+
+/// Check that we can attach a BPF program to a kernel tracepoint, providing
+/// additional options.
+#[test]
+fn test_object_tracepoint_with_opts() {
+    bump_rlimit_mlock();
+
+    let cookie_val = 42u16;
+    let mut obj = get_test_object("tracepoint.bpf.o");
+    let prog = obj
         .prog_mut("handle__tracepoint_with_cookie")
         .expect("Failed to find program");
 
@@ -1018,6 +2551,68 @@ fn test_object_uprobe_with_cookie() {
     assert_eq!(result, cookie_val.into());
 }
 
+/// Determine the offset of `addr`, an address within the code of this
+/// executable, in the executable's file.
+fn exe_file_offset(addr: usize) -> usize {
+    let maps = fs::read_to_string("/proc/self/maps").expect("Failed to read memory maps");
+    for line in maps.lines() {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let (start, end) = fields[0].split_once('-').expect("Invalid address range");
+        let start = usize::from_str_radix(start, 16).expect("Invalid start address");
+        let end = usize::from_str_radix(end, 16).expect("Invalid end address");
+        if (start..end).contains(&addr) {
+            let offset = usize::from_str_radix(fields[2], 16).expect("Invalid offset");
+            return addr - start + offset;
+        }
+    }
+    panic!("address {addr:#x} is not mapped")
+}
+
+/// Check that we can attach a BPF program to multiple uprobes at once.
+#[test]
+fn test_object_uprobe_multi() {
+    bump_rlimit_mlock();
+
+    let mut open_obj = open_test_object("uprobe.bpf.o");
+    let prog = open_obj
+        .prog_mut("handle__uprobe_with_cookie")
+        .expect("Failed to find program");
+    prog.set_attach_type(ProgramAttachType::TraceUprobeMulti);
+    let mut obj = open_obj.load().expect("failed to load object");
+    let prog = obj
+        .prog_mut("handle__uprobe_with_cookie")
+        .expect("Failed to find program");
+
+    let path = std::env::current_exe().expect("Failed to find executable name");
+    let offset = exe_file_offset(uprobe_target as *const () as usize);
+    let opts = UprobeMultiOpts {
+        offsets: vec![offset],
+        cookies: vec![42, 43],
+        ..Default::default()
+    };
+    let err = prog
+        .attach_uprobe_multi_with_opts(0, &path, opts)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let opts = UprobeMultiOpts {
+        offsets: vec![offset],
+        cookies: vec![42],
+        ..Default::default()
+    };
+    let _link = prog
+        .attach_uprobe_multi_with_opts(0, &path, opts)
+        .expect("Failed to attach prog");
+
+    let map = obj.map("ringbuf").expect("Failed to get ringbuf map");
+    let action = || {
+        let _ = uprobe_target();
+    };
+    let result = with_ringbuffer(map, action);
+
+    assert_eq!(result, 42);
+}
+
 /// Check that we can link multiple object files.
 #[test]
 fn test_object_link_files() {
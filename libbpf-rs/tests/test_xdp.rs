@@ -0,0 +1,93 @@
+use std::ffi::CString;
+use std::ptr;
+
+use serial_test::serial;
+
+mod test;
+use test::{bump_rlimit_mlock, prog_id};
+
+use nix::errno::Errno::EEXIST;
+use nix::unistd::close;
+
+use libbpf_rs::{Xdp, XdpFlags};
+
+// do all XDP tests on the lo network interface
+const LO_IFINDEX: i32 = 1;
+
+/// Load an XDP program passing all packets, returning its fd.
+fn load_xdp_pass() -> i32 {
+    // r0 = XDP_PASS; exit
+    let mut mov = libbpf_sys::bpf_insn {
+        code: (libbpf_sys::BPF_ALU64 | libbpf_sys::BPF_MOV | libbpf_sys::BPF_K) as u8,
+        imm: libbpf_sys::XDP_PASS as i32,
+        ..Default::default()
+    };
+    mov.set_dst_reg(0);
+    let exit = libbpf_sys::bpf_insn {
+        code: (libbpf_sys::BPF_JMP | libbpf_sys::BPF_EXIT) as u8,
+        ..Default::default()
+    };
+    let insns = [mov, exit];
+
+    let license = CString::new("GPL").unwrap();
+    let fd = unsafe {
+        libbpf_sys::bpf_prog_load(
+            libbpf_sys::BPF_PROG_TYPE_XDP,
+            ptr::null(),
+            license.as_ptr(),
+            insns.as_ptr(),
+            insns.len() as libbpf_sys::size_t,
+            ptr::null_mut(),
+        )
+    };
+    assert!(fd >= 0, "failed to load XDP program: {}", fd);
+    fd
+}
+
+#[test]
+#[serial]
+fn test_xdp_attach_replace_detach() {
+    bump_rlimit_mlock();
+
+    let fd1 = load_xdp_pass();
+    let fd2 = load_xdp_pass();
+    let xdp1 = Xdp::new(fd1);
+    let xdp2 = Xdp::new(fd2);
+
+    assert_eq!(Xdp::query_id(LO_IFINDEX, XdpFlags::SKB_MODE).unwrap(), 0);
+
+    xdp1.attach(LO_IFINDEX, XdpFlags::SKB_MODE | XdpFlags::UPDATE_IF_NOEXIST)
+        .expect("failed to attach XDP program");
+    let query = Xdp::query(LO_IFINDEX, XdpFlags::NONE).expect("failed to query");
+    assert_eq!(query.skb_prog_id, prog_id(fd1));
+    assert_eq!(query.drv_prog_id, 0);
+    assert_eq!(query.hw_prog_id, 0);
+
+    // Attaching again must not silently replace the program.
+    let err = xdp2
+        .attach(LO_IFINDEX, XdpFlags::SKB_MODE | XdpFlags::UPDATE_IF_NOEXIST)
+        .unwrap_err();
+    assert_eq!(err.errno(), Some(libc::EBUSY));
+
+    xdp2.replace(LO_IFINDEX, fd1, XdpFlags::SKB_MODE)
+        .expect("failed to replace XDP program");
+    assert_eq!(
+        Xdp::query_id(LO_IFINDEX, XdpFlags::SKB_MODE).unwrap(),
+        prog_id(fd2)
+    );
+
+    // The expected program is no longer attached.
+    let err = xdp1
+        .replace(LO_IFINDEX, fd1, XdpFlags::SKB_MODE)
+        .unwrap_err();
+    assert_eq!(err.errno(), Some(EEXIST as i32));
+    let err = xdp1.detach(LO_IFINDEX, XdpFlags::SKB_MODE).unwrap_err();
+    assert_eq!(err.errno(), Some(EEXIST as i32));
+
+    xdp2.detach(LO_IFINDEX, XdpFlags::SKB_MODE)
+        .expect("failed to detach XDP program");
+    assert_eq!(Xdp::query_id(LO_IFINDEX, XdpFlags::SKB_MODE).unwrap(), 0);
+
+    close(fd1).unwrap();
+    close(fd2).unwrap();
+}